@@ -7,3 +7,308 @@ pub mod time_signal;
 pub mod status;
 pub mod speaker;
 pub mod log;
+pub mod ignore;
+pub mod optout;
+pub mod reading;
+pub mod announce;
+pub mod queue;
+pub mod pause;
+pub mod resume;
+pub mod flood_protection;
+pub mod auto_join;
+pub mod auto_disconnect;
+pub mod permissions;
+pub mod command_settings;
+
+use crate::config::{self, GuildConfig};
+use std::sync::Arc;
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use tracing::info;
+use serenity::{async_trait, Result};
+use serenity::prelude::*;
+use serenity::http::Http;
+use serenity::builder::CreateApplicationCommand;
+use serenity::model::Permissions;
+use serenity::model::id::GuildId;
+use serenity::model::prelude::command::{Command, CommandOptionType};
+use serenity::model::application::interaction::{
+    InteractionResponseType,
+    application_command::ApplicationCommandInteraction
+};
+
+/// スラッシュコマンド
+#[async_trait]
+pub trait SlashCommand: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn register<'a>(&self, command: &'a mut CreateApplicationCommand) -> &'a mut CreateApplicationCommand;
+
+    async fn run(&self, ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<()>;
+
+    /// 実行に必要な権限。`/permissions`で上書きできる
+    fn required_permissions(&self) -> Permissions {
+        Permissions::empty()
+    }
+
+    /// サーバー内でのみ実行できる
+    fn guild_only(&self) -> bool {
+        true
+    }
+
+    /// `/commands`で無効にできる
+    fn can_disable(&self) -> bool {
+        true
+    }
+
+    /// サブコマンドの名前
+    fn subcommands(&self) -> Vec<String> {
+        let mut command = CreateApplicationCommand::default();
+        self.register(&mut command);
+        let Some(options) = command.0.get("options").and_then(|options| options.as_array()) else {
+            return Vec::new();
+        };
+        options.iter()
+            .filter(|option| {
+                let kind = option.get("type").and_then(|kind| kind.as_u64());
+                kind == Some(CommandOptionType::SubCommand as u64) || kind == Some(CommandOptionType::SubCommandGroup as u64)
+            })
+            .filter_map(|option| option.get("name").and_then(|name| name.as_str()).map(str::to_string))
+            .collect()
+    }
+}
+
+/// モジュールの`run`と`register`からコマンドを定義する
+macro_rules! command {
+    ($module:ident, $name:literal $(, $key:ident = $value:expr)* $(,)?) => {{
+        struct Command;
+
+        #[async_trait]
+        impl SlashCommand for Command {
+            fn name(&self) -> &'static str {
+                $name
+            }
+
+            fn register<'a>(&self, command: &'a mut CreateApplicationCommand) -> &'a mut CreateApplicationCommand {
+                $module::register(command)
+            }
+
+            async fn run(&self, ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<()> {
+                $module::run(ctx, interaction).await
+            }
+
+            $(command!(@$key $value);)*
+        }
+
+        &Command as &'static dyn SlashCommand
+    }};
+    (@permissions $value:expr) => {
+        fn required_permissions(&self) -> Permissions {
+            $value
+        }
+    };
+    (@guild_only $value:expr) => {
+        fn guild_only(&self) -> bool {
+            $value
+        }
+    };
+    (@can_disable $value:expr) => {
+        fn can_disable(&self) -> bool {
+            $value
+        }
+    };
+}
+
+/// コマンドの一覧。最初に参照されたときに一度だけ作る
+static COMMANDS: Lazy<Vec<&'static dyn SlashCommand>> = Lazy::new(|| {
+    vec![
+        command!(join, "join"),
+        command!(leave, "leave"),
+        command!(version, "version", guild_only = false),
+        command!(skip, "skip"),
+        command!(dictionary, "dictionary"),
+        command!(time_signal, "time-signal"),
+        command!(status, "status"),
+        command!(speaker, "speaker"),
+        command!(log, "log"),
+        command!(ignore, "ignore", permissions = Permissions::MANAGE_GUILD),
+        command!(optout, "optout"),
+        command!(reading, "reading", permissions = Permissions::MANAGE_GUILD),
+        command!(announce, "announce", permissions = Permissions::MANAGE_GUILD),
+        command!(queue, "queue"),
+        command!(pause, "pause"),
+        command!(resume, "resume"),
+        command!(flood_protection, "flood-protection", permissions = Permissions::MANAGE_GUILD),
+        command!(auto_join, "auto-join", permissions = Permissions::MANAGE_GUILD),
+        command!(auto_disconnect, "auto-disconnect", permissions = Permissions::MANAGE_GUILD),
+        command!(permissions, "permissions", permissions = Permissions::MANAGE_GUILD, can_disable = false),
+        command!(command_settings, "commands", permissions = Permissions::MANAGE_GUILD, can_disable = false),
+    ]
+});
+
+/// すべてのスラッシュコマンド
+pub fn all() -> &'static [&'static dyn SlashCommand] {
+    &COMMANDS
+}
+
+/// 名前からスラッシュコマンドを探す
+pub fn find(name: &str) -> Option<&'static dyn SlashCommand> {
+    all().iter().copied().find(|command| command.name() == name)
+}
+
+/// スラッシュコマンドを登録する内容を作る
+///
+/// DMで使えるかどうかはグローバルコマンドでのみ指定できる。
+pub fn create(command: &dyn SlashCommand, builder: &mut CreateApplicationCommand, global: bool) {
+    command.register(builder);
+    builder.name(command.name());
+    if global {
+        builder.dm_permission(!command.guild_only());
+    }
+    let permissions = command.required_permissions();
+    if !permissions.is_empty() {
+        builder.default_member_permissions(permissions);
+    }
+}
+
+fn create_all(global: bool) -> Vec<CreateApplicationCommand> {
+    all().iter().map(|&command| {
+        let mut builder = CreateApplicationCommand::default();
+        create(command, &mut builder, global);
+        builder
+    }).collect()
+}
+
+/// グローバルコマンドを登録する
+///
+/// `global`が`false`の場合は登録済みのグローバルコマンドを削除する。
+/// 登録済みのコマンドと変わらない場合は何もしない。
+pub async fn sync_global(http: &Http, global: bool) -> Result<()> {
+    let commands = if global { create_all(true) } else { Vec::new() };
+    let existing = Command::get_global_application_commands(http).await?;
+    if !needs_update(&commands, &existing, true) {
+        return Ok(());
+    }
+    info!(count = commands.len(), "register global commands");
+    Command::set_global_application_commands(http, |builder| builder.set_application_commands(commands)).await?;
+    Ok(())
+}
+
+/// ギルドコマンドを登録する
+///
+/// `global`が`true`の場合はグローバルコマンドと重複しないように登録済みのギルドコマンドを削除する。
+/// 登録済みのコマンドと変わらない場合は何もしない。
+pub async fn sync_guild(http: &Http, guild_id: GuildId, global: bool) -> Result<()> {
+    let commands = if global { Vec::new() } else { create_all(false) };
+    let existing = guild_id.get_application_commands(http).await?;
+    if !needs_update(&commands, &existing, false) {
+        return Ok(());
+    }
+    info!(guild_id = %guild_id, count = commands.len(), "register guild commands");
+    guild_id.set_application_commands(http, |builder| builder.set_application_commands(commands)).await?;
+    Ok(())
+}
+
+/// 登録済みのコマンドと比較する項目
+const COMMAND_KEYS: [&str; 5] = ["name", "description", "options", "default_member_permissions", "dm_permission"];
+
+/// 登録するコマンドと登録済みのコマンドが異なるか判定する
+fn needs_update(commands: &[CreateApplicationCommand], existing: &[Command], global: bool) -> bool {
+    if commands.len() != existing.len() {
+        return true;
+    }
+    let commands = commands.iter().filter_map(|command| serde_json::to_value(&command.0).ok()).collect::<Vec<_>>();
+    let keys = if global { &COMMAND_KEYS[..] } else { &COMMAND_KEYS[..4] };
+    let pick = |command: &Value| -> Value {
+        keys.iter().filter_map(|&key| Some((key.to_string(), command.get(key)?.clone()))).collect::<serde_json::Map<_, _>>().into()
+    };
+    commands.iter().any(|command| {
+        let existing = existing.iter()
+            .find(|existing| command.get("name").and_then(Value::as_str) == Some(existing.name.as_str()))
+            .and_then(|existing| serde_json::to_value(existing).ok());
+        match existing {
+            Some(existing) => !is_equivalent(&pick(command), &pick(&existing)),
+            None => true
+        }
+    })
+}
+
+/// 省略された項目と空の値を同じものとして比較する
+fn is_equivalent(a: &Value, b: &Value) -> bool {
+    let is_empty = |value: &Value| match value {
+        Value::Null | Value::Bool(false) => true,
+        Value::Array(array) => array.is_empty(),
+        Value::Object(map) => map.is_empty(),
+        _ => false
+    };
+    match (a, b) {
+        (Value::Object(a), Value::Object(b)) => a.keys().chain(b.keys()).all(|key| {
+            match (a.get(key), b.get(key)) {
+                (Some(a), Some(b)) => is_equivalent(a, b),
+                (Some(value), None) | (None, Some(value)) => is_empty(value),
+                (None, None) => true
+            }
+        }),
+        (Value::Array(a), Value::Array(b)) => a.len() == b.len() && a.iter().zip(b).all(|(a, b)| is_equivalent(a, b)),
+        (value, Value::Null) | (Value::Null, value) => is_empty(value),
+        (a, b) => a == b
+    }
+}
+
+/// インタラクションに対応するコマンドを実行する
+///
+/// 存在しない、無効にされている、権限がないなどの場合はエラーを返信する。
+pub async fn dispatch(ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<()> {
+    let error = match find(&interaction.data.name) {
+        None => Some("このコマンドはありません。"),
+        Some(command) if command.guild_only() && interaction.guild_id.is_none() => {
+            Some("このコマンドはサーバー内でのみ実行できます。")
+        },
+        Some(_) if interaction.guild_id.is_some() && guild_config(ctx, interaction).await.is_err() => {
+            Some("サーバーの設定を読み込めませんでした。")
+        },
+        Some(command) if is_disabled(ctx, command, interaction).await => {
+            Some("このコマンドはこのサーバーでは無効になっています。")
+        },
+        Some(command) if !permissions::is_permitted(ctx, command, interaction).await => {
+            Some("このコマンドを実行する権限がありません。")
+        },
+        Some(command) => return command.run(ctx, interaction).await
+    };
+
+    let msg = error.unwrap();
+    interaction.create_interaction_response(&ctx.http, |response| {
+        response.kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| message.ephemeral(true).content(msg))
+    }).await
+}
+
+async fn is_disabled(ctx: &Context, command: &dyn SlashCommand, interaction: &ApplicationCommandInteraction) -> bool {
+    let Some(guild_id) = interaction.guild_id else { return false; };
+    if !command.can_disable() {
+        return false;
+    }
+    let Ok(guild_config) = config::guild_config(ctx, guild_id).await else { return false; };
+    let is_disabled = guild_config.read().await.commands.disabled.contains(command.name());
+    is_disabled
+}
+
+/// コマンドを実行したギルドの設定
+///
+/// `dispatch`で読み込めることを確認しているので、コマンドの中では通常失敗しない。
+pub async fn guild_config(ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<Arc<RwLock<GuildConfig>>> {
+    let guild_id = interaction.guild_id.ok_or(serenity::Error::Other("Expected guild_id in interaction"))?;
+    config::guild_config(ctx, guild_id).await.map_err(|_| serenity::Error::Other("Failed to load guild config"))
+}
+
+#[test]
+fn test_is_equivalent() {
+    use serde_json::json;
+    let desired = json!({"name": "skip", "options": [{"type": 5, "name": "all", "description": "すべて"}]});
+    let existing = json!({"name": "skip", "options": [{"type": 5, "name": "all", "description": "すべて", "required": false, "choices": [], "min_value": null}]});
+    assert!(is_equivalent(&desired, &existing));
+    let existing = json!({"name": "skip", "options": [{"type": 5, "name": "all", "description": "すべて", "required": true}]});
+    assert!(!is_equivalent(&desired, &existing));
+    assert!(!is_equivalent(&desired, &json!({"name": "skip"})));
+    assert!(is_equivalent(&json!({"name": "version", "options": []}), &json!({"name": "version"})));
+}
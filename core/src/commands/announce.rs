@@ -0,0 +1,124 @@
+use crate::commands;
+use crate::config::VoiceAnnounceConfig;
+use std::collections::HashMap;
+use tracing::debug;
+use serenity::prelude::*;
+use serenity::Result;
+use serenity::builder::{CreateApplicationCommand, CreateApplicationCommandOption};
+use serenity::model::prelude::command::CommandOptionType;
+use serenity::model::application::interaction::{
+    InteractionResponseType,
+    application_command::{
+        CommandDataOptionValue,
+        ApplicationCommandInteraction
+    }
+};
+
+const EVENTS: [(&str, &str); 4] = [
+    ("入室", "join"),
+    ("退室", "leave"),
+    ("配信開始", "stream"),
+    ("チャンネル移動", "move")
+];
+
+fn list_message(config: &VoiceAnnounceConfig) -> String {
+    [
+        ("入室", &config.join),
+        ("退室", &config.leave),
+        ("配信開始", &config.stream),
+        ("チャンネル移動", &config.move_channel)
+    ].iter().map(|(name, announcement)| {
+        format!(
+            "{name}: {} `{}`",
+            if announcement.enabled {"有効"} else {"無効"},
+            announcement.template
+        )
+    }).collect::<Vec<_>>().join("\n")
+}
+
+pub async fn run(ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<()> {
+    let subcommand = &interaction.data.options[0];
+    let map = subcommand.options.iter().map(|option| {
+        (option.name.as_str(), option.resolved.as_ref().unwrap())
+    }).collect::<HashMap<_, _>>();
+
+    debug!(subcommand = %subcommand.name, options = ?map, "/announce");
+
+    let msg = {
+        let guild_config = commands::guild_config(ctx, interaction).await?;
+        let mut config_lock = guild_config.write().await;
+        let voice_announce = &mut config_lock.voice_announce;
+
+        let event = match map.get("event") {
+            Some(CommandDataOptionValue::String(event)) => event.as_str(),
+            _ => ""
+        };
+
+        match subcommand.name.as_str() {
+            "toggle" => {
+                let Some(CommandDataOptionValue::Boolean(enable)) = map.get("enable") else { panic!() };
+                voice_announce.get_mut(event).unwrap().enabled = *enable;
+                list_message(voice_announce)
+            },
+            "template" => {
+                let template = match map.get("text") {
+                    Some(CommandDataOptionValue::String(text)) => text.clone(),
+                    _ => VoiceAnnounceConfig::default_template(event).unwrap().to_string()
+                };
+                voice_announce.get_mut(event).unwrap().template = template;
+                list_message(voice_announce)
+            },
+            "list" => list_message(voice_announce),
+            _ => panic!("unexpected subcommand name")
+        }
+    };
+
+    interaction.create_interaction_response(&ctx.http, |response| {
+        response.kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| message.content(msg))
+    }).await
+}
+
+fn event_option(option: &mut CreateApplicationCommandOption) -> &mut CreateApplicationCommandOption {
+    option.name("event")
+        .description("読み上げるイベント")
+        .kind(CommandOptionType::String)
+        .required(true);
+    for (name, value) in EVENTS {
+        option.add_string_choice(name, value);
+    }
+    option
+}
+
+pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command.name("announce")
+        .description("ボイスチャンネルの入退室などの読み上げを設定します。")
+        .create_option(|option| {
+            option.name("toggle")
+                .description("イベントの読み上げを切り替えます。")
+                .kind(CommandOptionType::SubCommand)
+                .create_sub_option(event_option)
+                .create_sub_option(|option| {
+                    option.name("enable")
+                        .description("読み上げを有効にする場合はTrue")
+                        .kind(CommandOptionType::Boolean)
+                        .required(true)
+                })
+        })
+        .create_option(|option| {
+            option.name("template")
+                .description("読み上げる文章を設定します。{name}は名前に、{channel}は移動先のチャンネル名に置き換えられます。")
+                .kind(CommandOptionType::SubCommand)
+                .create_sub_option(event_option)
+                .create_sub_option(|option| {
+                    option.name("text")
+                        .description("読み上げる文章 (省略すると初期設定に戻します)")
+                        .kind(CommandOptionType::String)
+                })
+        })
+        .create_option(|option| {
+            option.name("list")
+                .description("現在の設定を表示します。")
+                .kind(CommandOptionType::SubCommand)
+        })
+}
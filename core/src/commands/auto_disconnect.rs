@@ -0,0 +1,105 @@
+use crate::commands;
+use crate::config::AutoDisconnectConfig;
+use std::collections::HashMap;
+use tracing::debug;
+use serenity::prelude::*;
+use serenity::Result;
+use serenity::builder::CreateApplicationCommand;
+use serenity::model::prelude::command::CommandOptionType;
+use serenity::model::application::interaction::{
+    InteractionResponseType,
+    application_command::{
+        CommandDataOptionValue,
+        ApplicationCommandInteraction
+    }
+};
+
+fn settings_message(config: &AutoDisconnectConfig) -> String {
+    if config.stay {
+        return "24時間モード: 有効 (自動で切断しません)".into();
+    }
+    let idle = if config.idle_minutes == 0 {
+        "無効".to_string()
+    } else {
+        format!("{}分", config.idle_minutes)
+    };
+    let announce = if config.announce {
+        format!("「{}」", config.message)
+    } else {
+        "無効".to_string()
+    };
+    format!(
+        "24時間モード: 無効\n全員が退室してから切断するまで: {}秒\n読み上げがないときに切断するまで: {idle}\n切断する前のお知らせ: {announce}",
+        config.grace_secs
+    )
+}
+
+pub async fn run(ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<()> {
+    let map = interaction.data.options.iter().map(|option| {
+        (option.name.as_str(), option.resolved.as_ref().unwrap())
+    }).collect::<HashMap<_, _>>();
+
+    debug!(options = ?map, "/auto-disconnect");
+
+    let msg = {
+        let guild_config = commands::guild_config(ctx, interaction).await?;
+        let mut config_lock = guild_config.write().await;
+        let auto_disconnect = &mut config_lock.auto_disconnect;
+        if let Some(CommandDataOptionValue::Integer(grace)) = map.get("grace") {
+            auto_disconnect.grace_secs = *grace as u64;
+        }
+        if let Some(CommandDataOptionValue::Integer(idle)) = map.get("idle") {
+            auto_disconnect.idle_minutes = *idle as u64;
+        }
+        if let Some(CommandDataOptionValue::Boolean(announce)) = map.get("announce") {
+            auto_disconnect.announce = *announce;
+        }
+        if let Some(CommandDataOptionValue::String(message)) = map.get("message") {
+            auto_disconnect.message = message.clone();
+        }
+        if let Some(CommandDataOptionValue::Boolean(stay)) = map.get("stay") {
+            auto_disconnect.stay = *stay;
+        }
+        settings_message(auto_disconnect)
+    };
+
+    interaction.create_interaction_response(&ctx.http, |response| {
+        response.kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| message.content(msg).allowed_mentions(|m| m.empty_parse()))
+    }).await
+}
+
+pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command.name("auto-disconnect")
+        .description("ボイスチャンネルから自動で切断する条件を設定します。")
+        .create_option(|option| {
+            option.name("grace")
+                .description("全員が退室してから切断するまでの秒数")
+                .kind(CommandOptionType::Integer)
+                .min_int_value(0)
+                .max_int_value(3600)
+        })
+        .create_option(|option| {
+            option.name("idle")
+                .description("メッセージが読み上げられないまま経過したら切断する分数。0で無効")
+                .kind(CommandOptionType::Integer)
+                .min_int_value(0)
+                .max_int_value(1440)
+        })
+        .create_option(|option| {
+            option.name("announce")
+                .description("切断する前にお知らせを読み上げます。")
+                .kind(CommandOptionType::Boolean)
+        })
+        .create_option(|option| {
+            option.name("message")
+                .description("切断する前に読み上げるお知らせ")
+                .kind(CommandOptionType::String)
+                .max_length(100)
+        })
+        .create_option(|option| {
+            option.name("stay")
+                .description("自動で切断せずに接続し続けます。(24時間モード)")
+                .kind(CommandOptionType::Boolean)
+        })
+}
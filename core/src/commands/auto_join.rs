@@ -0,0 +1,159 @@
+use crate::commands;
+use crate::config::{AutoJoinConfig, AutoJoinRule};
+use std::collections::HashMap;
+use tracing::debug;
+use serenity::prelude::*;
+use serenity::builder::CreateApplicationCommand;
+use serenity::model::channel::ChannelType;
+use serenity::model::prelude::command::CommandOptionType;
+use serenity::model::application::interaction::{
+    InteractionResponseType,
+    application_command::{
+        CommandDataOptionValue,
+        ApplicationCommandInteraction
+    }
+};
+
+fn list_message(config: &AutoJoinConfig) -> String {
+    let mut lines = config.rules.iter().map(|rule| {
+        let users = if rule.users.is_empty() {
+            "誰かが入室したとき".to_string()
+        } else {
+            rule.users.iter().map(|id| format!("<@{id}>")).collect::<Vec<_>>().join(" ") + "が入室したとき"
+        };
+        format!("<#{}> → <#{}> ({users})", rule.voice_channel, rule.text_channel)
+    }).collect::<Vec<_>>();
+    if lines.is_empty() {
+        lines.push("自動接続のルールはありません。".into());
+    }
+    lines.push(format!("起動時の再接続: {}", if config.rejoin_on_startup {"有効"} else {"無効"}));
+    lines.join("\n")
+}
+
+async fn run_inner(ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<String, String> {
+    let subcommand = &interaction.data.options[0];
+    let map = subcommand.options.iter().map(|option| {
+        (option.name.as_str(), option.resolved.as_ref().unwrap())
+    }).collect::<HashMap<_, _>>();
+
+    debug!(subcommand = %subcommand.name, options = ?map, "/auto-join");
+
+    let guild_config = commands::guild_config(ctx, interaction).await.map_err(|why| why.to_string())?;
+    let mut config_lock = guild_config.write().await;
+    let auto_join = &mut config_lock.auto_join;
+
+    match subcommand.name.as_str() {
+        "add" => {
+            let Some(CommandDataOptionValue::Channel(voice_channel)) = map.get("ボイスチャンネル") else { panic!() };
+            let text_channel = match map.get("テキストチャンネル") {
+                Some(CommandDataOptionValue::Channel(channel)) => channel.id,
+                _ => interaction.channel_id
+            };
+            let user = match map.get("ユーザー") {
+                Some(CommandDataOptionValue::User(user, _)) => Some(user.id),
+                _ => None
+            };
+            match auto_join.rules.iter_mut().find(|rule| rule.voice_channel == voice_channel.id) {
+                Some(rule) => {
+                    rule.text_channel = text_channel;
+                    match user {
+                        Some(user) if !rule.users.contains(&user) => rule.users.push(user),
+                        Some(_) => {},
+                        None => rule.users.clear()
+                    }
+                },
+                None => auto_join.rules.push(AutoJoinRule {
+                    voice_channel: voice_channel.id,
+                    text_channel,
+                    users: user.into_iter().collect()
+                })
+            }
+            Ok(list_message(auto_join))
+        },
+        "remove" => {
+            let Some(CommandDataOptionValue::Channel(voice_channel)) = map.get("ボイスチャンネル") else { panic!() };
+            let len = auto_join.rules.len();
+            auto_join.rules.retain(|rule| rule.voice_channel != voice_channel.id);
+            if auto_join.rules.len() == len {
+                return Err(format!("<#{}>の自動接続のルールはありません。", voice_channel.id));
+            }
+            Ok(list_message(auto_join))
+        },
+        "rejoin" => {
+            let Some(CommandDataOptionValue::Boolean(enable)) = map.get("enable") else { panic!() };
+            auto_join.rejoin_on_startup = *enable;
+            Ok(list_message(auto_join))
+        },
+        "list" => Ok(list_message(auto_join)),
+        _ => panic!("unexpected subcommand name")
+    }
+}
+
+pub async fn run(ctx: &Context, interaction: &ApplicationCommandInteraction) -> serenity::Result<()> {
+    let msg = run_inner(ctx, interaction).await;
+    interaction.create_interaction_response(&ctx.http, |response| {
+        response.kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| {
+                match msg {
+                    Ok(msg) => message.content(msg).allowed_mentions(|m| m.empty_parse()),
+                    Err(msg) => message.ephemeral(true).content(msg)
+                }
+            })
+    }).await
+}
+
+pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command.name("auto-join")
+        .description("ボイスチャンネルへの自動接続を設定します。")
+        .create_option(|option| {
+            option.name("add")
+                .description("ボイスチャンネルに入室があったときに自動で接続します。")
+                .kind(CommandOptionType::SubCommand)
+                .create_sub_option(|option| {
+                    option.name("ボイスチャンネル")
+                        .description("入室を監視するボイスチャンネル")
+                        .kind(CommandOptionType::Channel)
+                        .channel_types(&[ChannelType::Voice])
+                        .required(true)
+                })
+                .create_sub_option(|option| {
+                    option.name("テキストチャンネル")
+                        .description("読み上げるテキストチャンネル (省略するとこのチャンネル)")
+                        .kind(CommandOptionType::Channel)
+                        .channel_types(&[ChannelType::Text, ChannelType::Voice])
+                })
+                .create_sub_option(|option| {
+                    option.name("ユーザー")
+                        .description("このユーザーが入室したときのみ接続します。")
+                        .kind(CommandOptionType::User)
+                })
+        })
+        .create_option(|option| {
+            option.name("remove")
+                .description("自動接続のルールを削除します。")
+                .kind(CommandOptionType::SubCommand)
+                .create_sub_option(|option| {
+                    option.name("ボイスチャンネル")
+                        .description("ルールを削除するボイスチャンネル")
+                        .kind(CommandOptionType::Channel)
+                        .channel_types(&[ChannelType::Voice])
+                        .required(true)
+                })
+        })
+        .create_option(|option| {
+            option.name("rejoin")
+                .description("起動時に前回接続していたボイスチャンネルに再接続します。")
+                .kind(CommandOptionType::SubCommand)
+                .create_sub_option(|option| {
+                    option.name("enable")
+                        .description("再接続する場合はTrue")
+                        .kind(CommandOptionType::Boolean)
+                        .required(true)
+                })
+        })
+        .create_option(|option| {
+            option.name("list")
+                .description("自動接続の設定を表示します。")
+                .kind(CommandOptionType::SubCommand)
+        })
+}
@@ -0,0 +1,108 @@
+use crate::commands;
+use crate::config::CommandConfig;
+use std::collections::HashMap;
+use tracing::debug;
+use serenity::prelude::*;
+use serenity::builder::CreateApplicationCommand;
+use serenity::model::prelude::command::CommandOptionType;
+use serenity::model::application::interaction::{
+    InteractionResponseType,
+    application_command::{
+        CommandDataOptionValue,
+        ApplicationCommandInteraction
+    }
+};
+
+fn list_message(config: &CommandConfig) -> String {
+    commands::all().iter().map(|command| {
+        let state = if config.disabled.contains(command.name()) {"無効"} else {"有効"};
+        format!("`/{}`: {state}", command.name())
+    }).collect::<Vec<_>>().join("\n")
+}
+
+async fn run_inner(ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<String, String> {
+    let subcommand = &interaction.data.options[0];
+    let map = subcommand.options.iter().map(|option| {
+        (option.name.as_str(), option.resolved.as_ref().unwrap())
+    }).collect::<HashMap<_, _>>();
+
+    debug!(subcommand = %subcommand.name, options = ?map, "/commands");
+
+    let command = match map.get("command") {
+        Some(CommandDataOptionValue::String(name)) => {
+            let name = name.trim().trim_start_matches('/');
+            let Some(command) = commands::find(name) else {
+                return Err(format!("`/{name}`というコマンドはありません。"));
+            };
+            if !command.can_disable() {
+                return Err(format!("`/{name}`は無効にできません。"));
+            }
+            Some(command)
+        },
+        _ => None
+    };
+
+    let guild_config = commands::guild_config(ctx, interaction).await.map_err(|why| why.to_string())?;
+    let mut config_lock = guild_config.write().await;
+    let config = &mut config_lock.commands;
+
+    match subcommand.name.as_str() {
+        "enable" => {
+            let name = command.unwrap().name();
+            config.disabled.remove(name);
+            Ok(format!("`/{name}`を有効にしました。"))
+        },
+        "disable" => {
+            let name = command.unwrap().name();
+            config.disabled.insert(name.to_string());
+            Ok(format!("`/{name}`を無効にしました。"))
+        },
+        "list" => Ok(list_message(config)),
+        _ => panic!("unexpected subcommand name")
+    }
+}
+
+pub async fn run(ctx: &Context, interaction: &ApplicationCommandInteraction) -> serenity::Result<()> {
+    let msg = run_inner(ctx, interaction).await;
+    interaction.create_interaction_response(&ctx.http, |response| {
+        response.kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| {
+                match msg {
+                    Ok(msg) => message.content(msg),
+                    Err(msg) => message.ephemeral(true).content(msg)
+                }
+            })
+    }).await
+}
+
+pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command.name("commands")
+        .description("このサーバーで使えるコマンドを設定します。")
+        .create_option(|option| {
+            option.name("enable")
+                .description("コマンドを有効にします。")
+                .kind(CommandOptionType::SubCommand)
+                .create_sub_option(|option| {
+                    option.name("command")
+                        .description("コマンド名")
+                        .kind(CommandOptionType::String)
+                        .required(true)
+                })
+        })
+        .create_option(|option| {
+            option.name("disable")
+                .description("コマンドを無効にします。")
+                .kind(CommandOptionType::SubCommand)
+                .create_sub_option(|option| {
+                    option.name("command")
+                        .description("コマンド名")
+                        .kind(CommandOptionType::String)
+                        .required(true)
+                })
+        })
+        .create_option(|option| {
+            option.name("list")
+                .description("コマンドの有効・無効を表示します。")
+                .kind(CommandOptionType::SubCommand)
+        })
+}
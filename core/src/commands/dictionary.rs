@@ -4,6 +4,7 @@ mod reset;
 mod export;
 mod import;
 mod search;
+mod lint;
 
 use serenity::prelude::*;
 use serenity::Result;
@@ -21,6 +22,7 @@ pub async fn run(ctx: &Context, interaction: &ApplicationCommandInteraction) ->
         "export" => export::run(ctx, interaction).await,
         "import" => import::run(ctx, interaction).await,
         "search" => search::run(ctx, interaction).await,
+        "lint" => lint::run(ctx, interaction).await,
         _ => panic!("unexpected subcommand name")
     }
 }
@@ -99,4 +101,9 @@ pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicatio
                         .description("検索する単語")
                 })
         })
+        .create_option(|option| {
+            option.name("lint")
+                .description("辞書に登録されている単語に問題がないか調べます。")
+                .kind(CommandOptionType::SubCommand)
+        })
 }
@@ -1,5 +1,5 @@
+use crate::commands;
 use std::collections::HashMap;
-use crate::ConfigData;
 use dictionary::DictItem;
 use tracing::debug;
 use serenity::prelude::*;
@@ -42,17 +42,8 @@ pub async fn run(ctx: &Context, interaction: &ApplicationCommandInteraction) ->
 
     let item = DictItem { key, value, is_regex };
 
-    let guild_id = interaction.guild_id.unwrap();
-
-    let is_updated = {
-        let data_read = ctx.data.read().await;
-        let config = data_read.get::<ConfigData>().unwrap();
-        let mut lock = config.lock().unwrap();
-        let config = lock.guild_config_mut(guild_id);
-        let is_updated = config.dictionary.insert(item.clone()).is_some();
-        let _ = config.save(guild_id);
-        is_updated
-    };
+    let guild_config = commands::guild_config(ctx, interaction).await?;
+    let is_updated = guild_config.write().await.dictionary.insert(item.clone()).is_some();
 
     interaction.create_interaction_response(&ctx.http, |response| {
         response.kind(InteractionResponseType::ChannelMessageWithSource)
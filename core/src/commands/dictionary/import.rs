@@ -1,5 +1,5 @@
+use crate::commands;
 use std::collections::HashMap;
-use crate::ConfigData;
 use dictionary::DictItem;
 use tracing::debug;
 use serenity::prelude::*;
@@ -34,20 +34,17 @@ async fn run_inner(ctx: &Context, interaction: &ApplicationCommandInteraction) -
         return Err("ファイルの取得に失敗しました。");
     };
 
-    let guild_id = interaction.guild_id.unwrap();
-
     match format {
         "json" => {
             let Ok(items) = response.json::<Vec<DictItem>>().await else {
                 return Err("無効なJSONデータです。");
             };
+            if items.iter().any(|item| item.is_regex && regex::Regex::new(&item.key).is_err()) {
+                return Err("無効な正規表現が含まれています。");
+            }
             {
-                let data_read = ctx.data.read().await;
-                let config = data_read.get::<ConfigData>().expect("Expected ConfigData in TypeMap.");
-                let mut lock = config.lock().unwrap();
-                let config = lock.guild_config_mut(guild_id);
-                config.dictionary.extend(items);
-                let _ = config.save(guild_id);
+                let guild_config = commands::guild_config(ctx, interaction).await.map_err(|_| "サーバーの設定を読み込めませんでした。")?;
+                guild_config.write().await.dictionary.extend(items);
             }
             Ok("辞書をインポートしました。")
         },
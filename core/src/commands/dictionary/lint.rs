@@ -0,0 +1,54 @@
+use crate::commands;
+use tracing::debug;
+use serenity::prelude::*;
+use serenity::Result;
+use serenity::utils::Color;
+use serenity::model::application::interaction::{
+    InteractionResponseType,
+    application_command::ApplicationCommandInteraction
+};
+
+/// 埋め込みに表示する問題の数
+const MAX_LINTS: usize = 20;
+
+pub async fn run(ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<()> {
+    debug!("/dictionary lint");
+
+    let dict = {
+        let guild_config = commands::guild_config(ctx, interaction).await?;
+        let dict = guild_config.read().await.dictionary.clone();
+        dict
+    };
+    // 項目数の2乗に比例するので、ロックの外で他のタスクを止めないように調べる
+    let lints = tokio::task::spawn_blocking(move || dict.lint()).await
+        .map_err(|_| serenity::Error::Other("Failed to lint dictionary"))?;
+
+    let mut description = lints.iter()
+        .take(MAX_LINTS)
+        .map(|lint| format!("{} {lint}", if lint.is_error() {"❌"} else {"⚠️"}))
+        .collect::<Vec<_>>()
+        .join("\n");
+    if lints.len() > MAX_LINTS {
+        description.push_str(&format!("\n他{}件", lints.len() - MAX_LINTS));
+    }
+
+    interaction.create_interaction_response(&ctx.http, |response| {
+        response.kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| {
+                if lints.is_empty() {
+                    message.ephemeral(true)
+                        .embed(|embed| {
+                            embed.title("辞書に問題は見つかりませんでした。")
+                                .color(Color::from_rgb(0x66, 0xbb, 0x6a))
+                        })
+                } else {
+                    message.ephemeral(true)
+                        .embed(|embed| {
+                            embed.title(format!("辞書に{}件の問題が見つかりました。", lints.len()))
+                                .description(description)
+                                .color(Color::from_rgb(0xff, 0xa7, 0x26))
+                        })
+                }
+            })
+    }).await
+}
@@ -1,4 +1,4 @@
-use crate::ConfigData;
+use crate::commands;
 use tracing::debug;
 use serenity::prelude::*;
 use serenity::Result;
@@ -19,17 +19,8 @@ pub async fn run(ctx: &Context, interaction: &ApplicationCommandInteraction) ->
 
     debug!(key = %key, "/dictionary remove");
 
-    let guild_id = interaction.guild_id.unwrap();
-
-    let is_removed = {
-        let data_read = ctx.data.read().await;
-        let config = data_read.get::<ConfigData>().expect("Expected ConfigData in TypeMap.");
-        let mut lock = config.lock().unwrap();
-        let config = lock.guild_config_mut(guild_id);
-        let is_removed = config.dictionary.remove(key).is_some();
-        let _ = config.save(guild_id);
-        is_removed
-    };
+    let guild_config = commands::guild_config(ctx, interaction).await?;
+    let is_removed = guild_config.write().await.dictionary.remove(key).is_some();
 
     interaction.create_interaction_response(&ctx.http, |response| {
         response.kind(InteractionResponseType::ChannelMessageWithSource)
@@ -1,4 +1,4 @@
-use crate::ConfigData;
+use crate::commands;
 use tracing::debug;
 use serenity::prelude::*;
 use serenity::Result;
@@ -38,19 +38,13 @@ pub async fn run(ctx: &Context, interaction: &ApplicationCommandInteraction) ->
 
     debug!(reset = %msg_interaction.data.custom_id, "/dictionary reset");
 
-    let guild_id = interaction.guild_id.unwrap();
-
     let response_message = match msg_interaction.data.custom_id.as_str() {
         "reset_cancel" => {
             "リセットをキャンセルしました。"
         },
         "reset_do" => {
-            let data_read = ctx.data.read().await;
-            let config = data_read.get::<ConfigData>().expect("Expected ConfigData in TypeMap.");
-            let mut lock = config.lock().unwrap();
-            let config = lock.guild_config_mut(guild_id);
-            config.dictionary.clear();
-            let _ = config.save(guild_id);
+            let guild_config = commands::guild_config(ctx, interaction).await?;
+            guild_config.write().await.dictionary.clear();
             "辞書をリセットしました。"
         },
         _ => unreachable!()
@@ -1,4 +1,4 @@
-use crate::ConfigData;
+use crate::commands;
 use tracing::debug;
 use serenity::prelude::*;
 use serenity::Result;
@@ -19,13 +19,10 @@ pub async fn run(ctx: &Context, interaction: &ApplicationCommandInteraction) ->
 
     debug!(key = %key, "/dictionary search");
 
-    let guild_id = interaction.guild_id.unwrap();
-
     let item = {
-        let data_read = ctx.data.read().await;
-        let config = data_read.get::<ConfigData>().expect("Expected ConfigData in TypeMap.");
-        let mut lock = config.lock().unwrap();
-        let dict = &lock.guild_config(guild_id).dictionary;
+        let guild_config = commands::guild_config(ctx, interaction).await?;
+        let lock = guild_config.read().await;
+        let dict = &lock.dictionary;
         dict.get(key).cloned()
     };
 
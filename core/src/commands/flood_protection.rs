@@ -0,0 +1,87 @@
+use crate::commands;
+use crate::config::FloodConfig;
+use std::collections::HashMap;
+use tracing::debug;
+use serenity::prelude::*;
+use serenity::Result;
+use serenity::builder::CreateApplicationCommand;
+use serenity::model::prelude::command::CommandOptionType;
+use serenity::model::application::interaction::{
+    InteractionResponseType,
+    application_command::{
+        CommandDataOptionValue,
+        ApplicationCommandInteraction
+    }
+};
+
+fn settings_message(config: &FloodConfig) -> String {
+    let limit = |n: u64| if n == 0 {"無制限".to_string()} else {format!("{n}件")};
+    format!(
+        "読み上げ待ちの上限: {}\nユーザーごとの1分間の上限: {}\n連続したメッセージをまとめる: {}\n上限を超えたとき: {}",
+        limit(config.max_pending as u64),
+        limit(config.max_per_minute as u64),
+        if config.coalesce {"有効"} else {"無効"},
+        if config.drop_oldest {"古いものから破棄する"} else {"新しいメッセージを読み上げない"}
+    )
+}
+
+pub async fn run(ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<()> {
+    let map = interaction.data.options.iter().map(|option| {
+        (option.name.as_str(), option.resolved.as_ref().unwrap())
+    }).collect::<HashMap<_, _>>();
+
+    debug!(options = ?map, "/flood-protection");
+
+    let msg = {
+        let guild_config = commands::guild_config(ctx, interaction).await?;
+        let mut config_lock = guild_config.write().await;
+        let flood = &mut config_lock.flood;
+        if let Some(CommandDataOptionValue::Integer(max_pending)) = map.get("max-pending") {
+            flood.max_pending = *max_pending as usize;
+        }
+        if let Some(CommandDataOptionValue::Integer(per_minute)) = map.get("per-minute") {
+            flood.max_per_minute = *per_minute as u32;
+        }
+        if let Some(CommandDataOptionValue::Boolean(coalesce)) = map.get("coalesce") {
+            flood.coalesce = *coalesce;
+        }
+        if let Some(CommandDataOptionValue::Boolean(drop_oldest)) = map.get("drop-oldest") {
+            flood.drop_oldest = *drop_oldest;
+        }
+        settings_message(flood)
+    };
+
+    interaction.create_interaction_response(&ctx.http, |response| {
+        response.kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| message.content(msg))
+    }).await
+}
+
+pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command.name("flood-protection")
+        .description("大量のメッセージで読み上げが滞らないように制限します。")
+        .create_option(|option| {
+            option.name("max-pending")
+                .description("読み上げ待ちにできるメッセージの最大数。0で無制限")
+                .kind(CommandOptionType::Integer)
+                .min_int_value(0)
+                .max_int_value(1000)
+        })
+        .create_option(|option| {
+            option.name("per-minute")
+                .description("ユーザーごとに1分間に読み上げるメッセージの最大数。0で無制限")
+                .kind(CommandOptionType::Integer)
+                .min_int_value(0)
+                .max_int_value(1000)
+        })
+        .create_option(|option| {
+            option.name("coalesce")
+                .description("同じユーザーの連続したメッセージをまとめて読み上げます。")
+                .kind(CommandOptionType::Boolean)
+        })
+        .create_option(|option| {
+            option.name("drop-oldest")
+                .description("上限を超えたときに古いメッセージから破棄します。")
+                .kind(CommandOptionType::Boolean)
+        })
+}
@@ -0,0 +1,157 @@
+use crate::commands;
+use std::collections::HashMap;
+use tracing::debug;
+use serenity::prelude::*;
+use serenity::builder::CreateApplicationCommand;
+use serenity::model::prelude::command::CommandOptionType;
+use serenity::model::application::interaction::{
+    InteractionResponseType,
+    application_command::{
+        CommandDataOptionValue,
+        ApplicationCommandInteraction
+    }
+};
+
+async fn run_inner(ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<String, String> {
+    let subcommand = &interaction.data.options[0];
+    let map = subcommand.options.iter().map(|option| {
+        (option.name.as_str(), option.resolved.as_ref().unwrap())
+    }).collect::<HashMap<_, _>>();
+    let remove = matches!(map.get("解除"), Some(CommandDataOptionValue::Boolean(true)));
+
+    debug!(subcommand = %subcommand.name, remove = %remove, "/ignore");
+
+    let guild_config = commands::guild_config(ctx, interaction).await.map_err(|why| why.to_string())?;
+    let mut lock = guild_config.write().await;
+    let ignore = &mut lock.ignore;
+
+    match subcommand.name.as_str() {
+        "user" => {
+            let Some(CommandDataOptionValue::User(user, _)) = map.get("ユーザー") else { panic!() };
+            if remove {
+                if !ignore.users.remove(&user.id) {
+                    return Err(format!("<@{}>は無視リストに登録されていません。", user.id));
+                }
+                Ok(format!("<@{}>を無視リストから削除しました。", user.id))
+            } else {
+                ignore.users.insert(user.id);
+                Ok(format!("<@{}>のメッセージを読み上げないようにしました。", user.id))
+            }
+        },
+        "role" => {
+            let Some(CommandDataOptionValue::Role(role)) = map.get("ロール") else { panic!() };
+            if remove {
+                if !ignore.roles.remove(&role.id) {
+                    return Err(format!("<@&{}>は無視リストに登録されていません。", role.id));
+                }
+                Ok(format!("<@&{}>を無視リストから削除しました。", role.id))
+            } else {
+                ignore.roles.insert(role.id);
+                Ok(format!("<@&{}>のメンバーのメッセージを読み上げないようにしました。", role.id))
+            }
+        },
+        "prefix" => {
+            let Some(CommandDataOptionValue::String(prefix)) = map.get("接頭辞") else { panic!() };
+            if prefix.is_empty() {
+                return Err("空の接頭辞は登録できません。".into());
+            }
+            if remove {
+                let Some(index) = ignore.prefixes.iter().position(|p| p == prefix) else {
+                    return Err(format!("`{prefix}`は登録されていません。"));
+                };
+                ignore.prefixes.remove(index);
+                Ok(format!("`{prefix}`を削除しました。"))
+            } else {
+                if !ignore.prefixes.contains(prefix) {
+                    ignore.prefixes.push(prefix.clone());
+                }
+                Ok(format!("`{prefix}`で始まるメッセージを読み上げないようにしました。"))
+            }
+        },
+        "list" => {
+            let users = ignore.users.iter().map(|id| format!("<@{id}>")).collect::<Vec<_>>();
+            let roles = ignore.roles.iter().map(|id| format!("<@&{id}>")).collect::<Vec<_>>();
+            let prefixes = ignore.prefixes.iter().map(|p| format!("`{p}`")).collect::<Vec<_>>();
+            let join = |v: Vec<String>| if v.is_empty() {"なし".to_string()} else {v.join(" ")};
+            Ok(format!(
+                "ユーザー: {}\nロール: {}\n接頭辞: {}\n読み上げ拒否: {}人",
+                join(users),
+                join(roles),
+                join(prefixes),
+                ignore.opt_out_users.len()
+            ))
+        },
+        _ => panic!("unexpected subcommand name")
+    }
+}
+
+pub async fn run(ctx: &Context, interaction: &ApplicationCommandInteraction) -> serenity::Result<()> {
+    let msg = run_inner(ctx, interaction).await;
+    interaction.create_interaction_response(&ctx.http, |response| {
+        response.kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| {
+                match msg {
+                    Ok(msg) => message.content(msg).allowed_mentions(|m| m.empty_parse()),
+                    Err(msg) => message.ephemeral(true).content(msg)
+                }
+            })
+    }).await
+}
+
+pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command.name("ignore")
+        .description("読み上げないメッセージを設定します。")
+        .create_option(|option| {
+            option.name("user")
+                .description("指定したユーザーのメッセージを読み上げないようにします。")
+                .kind(CommandOptionType::SubCommand)
+                .create_sub_option(|option| {
+                    option.name("ユーザー")
+                        .kind(CommandOptionType::User)
+                        .required(true)
+                        .description("読み上げないユーザー")
+                })
+                .create_sub_option(|option| {
+                    option.name("解除")
+                        .kind(CommandOptionType::Boolean)
+                        .description("無視リストから削除する場合はTrue")
+                })
+        })
+        .create_option(|option| {
+            option.name("role")
+                .description("指定したロールのメンバーのメッセージを読み上げないようにします。")
+                .kind(CommandOptionType::SubCommand)
+                .create_sub_option(|option| {
+                    option.name("ロール")
+                        .kind(CommandOptionType::Role)
+                        .required(true)
+                        .description("読み上げないロール")
+                })
+                .create_sub_option(|option| {
+                    option.name("解除")
+                        .kind(CommandOptionType::Boolean)
+                        .description("無視リストから削除する場合はTrue")
+                })
+        })
+        .create_option(|option| {
+            option.name("prefix")
+                .description("指定した文字列で始まるメッセージを読み上げないようにします。")
+                .kind(CommandOptionType::SubCommand)
+                .create_sub_option(|option| {
+                    option.name("接頭辞")
+                        .kind(CommandOptionType::String)
+                        .required(true)
+                        .description("読み上げないメッセージの先頭の文字列")
+                })
+                .create_sub_option(|option| {
+                    option.name("解除")
+                        .kind(CommandOptionType::Boolean)
+                        .description("無視リストから削除する場合はTrue")
+                })
+        })
+        .create_option(|option| {
+            option.name("list")
+                .description("無視リストを表示します。")
+                .kind(CommandOptionType::SubCommand)
+        })
+}
@@ -1,4 +1,4 @@
-use crate::{TextChannelId, ConnectedChannel};
+use crate::session;
 use tracing::debug;
 use serenity::prelude::*;
 use serenity::builder::CreateApplicationCommand;
@@ -21,7 +21,7 @@ async fn run_inner(ctx: &Context, interaction: &ApplicationCommandInteraction) -
     let guild = ctx.cache.guild(guild_id).unwrap();
 
     let channel_id = if let Some(CommandDataOptionValue::Channel(channel)) =
-        &options.get(0).and_then(|opt| opt.resolved.as_ref())
+        &options.first().and_then(|opt| opt.resolved.as_ref())
     {
         Some(channel.id)
     } else {
@@ -36,24 +36,11 @@ async fn run_inner(ctx: &Context, interaction: &ApplicationCommandInteraction) -
         return Err("接続に失敗しました。");
     };
 
-    let manager = songbird::get(ctx).await.unwrap();
-    let (_handle, result) = manager.join(guild_id, connect_to).await;
-    if result.is_err() {
+    // メッセージを読むテキストチャンネルも設定する
+    if session::connect(ctx, guild_id, connect_to, interaction.channel_id).await.is_err() {
         return Err("接続に失敗しました。");
     }
 
-    // メッセージを読むテキストチャンネルを設定する
-    {
-        let data_read = ctx.data.read().await;
-        let channel_id = data_read.get::<TextChannelId>().unwrap();
-        let mut lock = channel_id.lock().unwrap();
-        lock.insert(guild_id, interaction.channel_id);
-        let connected = data_read.get::<ConnectedChannel>().unwrap();
-        let mut lock = connected.lock().unwrap();
-        lock.insert(guild_id, connect_to);
-    }
-    
-
     Ok(format!("<#{connect_to}>に接続しました。"))
 }
 
@@ -1,4 +1,4 @@
-use crate::type_map::ConnectedChannel;
+use crate::session;
 use tracing::debug;
 use serenity::Result;
 use serenity::prelude::*;
@@ -13,20 +13,7 @@ pub async fn run(ctx: &Context, interaction: &ApplicationCommandInteraction) ->
 
     let guild_id = interaction.guild_id.unwrap();
 
-    let manager = songbird::get(ctx).await.unwrap();
-
-    {
-        let data_read = ctx.data.read().await;
-        let connected = data_read.get::<ConnectedChannel>().unwrap();
-        let mut lock = connected.lock().unwrap();
-        lock.remove(&guild_id);
-    }
-
-    if let Some(handle) = manager.get(guild_id) {
-        let handler = handle.lock().await;
-        handler.queue().modify_queue(|q| q.clear());
-    }
-    let success =  manager.leave(guild_id).await.is_ok();
+    let success = session::disconnect(ctx, guild_id).await.is_ok();
 
     interaction.create_interaction_response(&ctx.http, |response| {
         response.kind(InteractionResponseType::ChannelMessageWithSource)
@@ -1,10 +1,17 @@
-use std::path::Path;
-use tracing::debug;
+use std::io::Write;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use chrono_tz::Tz;
+use flate2::{write::GzEncoder, Compression};
+use tracing::{debug, error};
 use serenity::prelude::*;
 use serenity::Result;
+use serenity::http::StatusCode;
 use serenity::builder::CreateApplicationCommand;
 use serenity::model::prelude::command::CommandOptionType;
 use serenity::model::channel::AttachmentType;
+use serenity::model::guild::PremiumTier;
 use serenity::model::application::interaction::{
     InteractionResponseType,
     application_command::{
@@ -14,63 +21,320 @@ use serenity::model::application::interaction::{
 };
 
 use crate::config::GlobalConfig;
+use crate::log::LogQuery;
 
-pub async fn run(ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<()> {
-    let admins = GlobalConfig::load().map(|config| config.admin_user).unwrap_or_default();
-    if !admins.contains(&interaction.user.id) {
-        return interaction.create_interaction_response(&ctx.http, |response| {
-            response.kind(InteractionResponseType::ChannelMessageWithSource)
-                .interaction_response_data(|message| {
-                    message.ephemeral(true).content("このコマンドを実行する権限がありません。")
-                })
-        }).await;
-    }
+const MB: usize = 1000 * 1000;
 
+const LEVELS: [&str; 5] = ["error", "warn", "info", "debug", "trace"];
+
+/// `yyyy-mm-dd`または`yyyy-mm-dd hh:mm`形式の時刻。`end`の場合はその日(分)の終わりにする
+fn parse_time(time: &str, timezone: Tz, end: bool) -> Option<DateTime<Tz>> {
+    let time = time.trim();
+    let time = match NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M") {
+        Ok(time) if end => time + chrono::Duration::milliseconds(59_999),
+        Ok(time) => time,
+        Err(_) => {
+            let date = NaiveDate::parse_from_str(time, "%Y-%m-%d").ok()?;
+            if end { date.and_hms_milli_opt(23, 59, 59, 999)? } else { date.and_hms_opt(0, 0, 0)? }
+        }
+    };
+    time.and_local_timezone(timezone).earliest()
+}
 
-    let options = &interaction.data.options;
-    let CommandDataOptionValue::String(date) = options[0].resolved.as_ref().unwrap() else {
-        panic!()
+/// オプションから絞り込む条件を作る。指定が正しくない場合は返信するメッセージを返す
+fn query(map: &HashMap<&str, &CommandDataOptionValue>, timezone: Tz) -> std::result::Result<LogQuery, &'static str> {
+    let get = |name: &str| match map.get(name) {
+        Some(CommandDataOptionValue::String(value)) => Some(value.as_str()),
+        _ => None
+    };
+    let parse = |name: &str, end: bool| match get(name) {
+        Some(time) => parse_time(time, timezone, end)
+            .map(Some)
+            .ok_or("日時の形式が無効です。yyyy-mm-ddまたはyyyy-mm-dd hh:mm形式で指定してください。"),
+        None => Ok(None)
     };
 
-    debug!(date = %date, "/log");
+    let now = chrono::Utc::now().with_timezone(&timezone);
+    let (from, to) = match parse("date", false)? {
+        Some(from) => (from, parse("date", true)?.unwrap()),
+        None => {
+            let to = parse("to", true)?.unwrap_or(now);
+            let from = match parse("from", false)? {
+                Some(from) => from,
+                None => parse_time(&to.format("%Y-%m-%d").to_string(), timezone, false).unwrap()
+            };
+            (from, to)
+        }
+    };
+    if from > to {
+        return Err("開始日時が終了日時より後になっています。");
+    }
 
-    if !regex::Regex::new("^[0-9]{4}-(0[1-9]|1[0-2])-(0[1-9]|[12][0-9]|3[01])$").unwrap().is_match(date) {
-        return interaction.create_interaction_response(&ctx.http, |response| {
-            response.kind(InteractionResponseType::ChannelMessageWithSource)
-                .interaction_response_data(|message| {
-                    message.ephemeral(true).content("日付の形式が無効です。yyyy-mm--dd形式で指定してください。")
-                })
-        }).await;
+    let guild_id = match get("guild") {
+        Some(guild_id) => Some(guild_id.trim().parse().map_err(|_| "サーバーIDが無効です。")?),
+        None => None
+    };
+    let tail = match map.get("tail") {
+        Some(CommandDataOptionValue::Integer(tail)) => Some(*tail as usize),
+        _ => None
+    };
+
+    Ok(LogQuery {
+        from,
+        to,
+        level: get("level").and_then(|level| level.parse().ok()),
+        guild_id,
+        command: get("command").map(|command| command.trim_start_matches('/').to_string()),
+        tail
+    })
+}
+
+/// サーバーのブーストレベルに応じてアップロードできるファイルの大きさ
+fn upload_limit(ctx: &Context, interaction: &ApplicationCommandInteraction) -> usize {
+    let tier = interaction.guild_id
+        .and_then(|guild_id| guild_id.to_guild_cached(&ctx.cache))
+        .map(|guild| guild.premium_tier);
+    match tier {
+        Some(PremiumTier::Tier3) => 100 * MB,
+        Some(PremiumTier::Tier2) => 50 * MB,
+        _ => 10 * MB
+    }
+}
+
+/// 上限までしか書き込めない`Vec`
+struct LimitedWriter {
+    data: Vec<u8>,
+    limit: usize,
+    exceeded: bool
+}
+
+impl Write for LimitedWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.data.len() + buf.len() > self.limit {
+            self.exceeded = true;
+            return Err(std::io::Error::other("exceeded upload limit"));
+        }
+        self.data.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+enum LogOutput {
+    Empty,
+    TooLarge,
+    /// データと圧縮したか
+    File(Vec<u8>, bool)
+}
+
+/// 上限を超えるまではそのまま書き込み、超えたらgzipで圧縮しながら書き込む
+enum Attachment {
+    Plain(LimitedWriter),
+    Compressed(GzEncoder<LimitedWriter>)
+}
+
+impl Attachment {
+    fn new(limit: usize) -> Self {
+        Self::Plain(LimitedWriter { data: Vec::new(), limit, exceeded: false })
+    }
+
+    /// 圧縮しても上限を超えた
+    fn is_too_large(&self) -> bool {
+        match self {
+            Self::Plain(_) => false,
+            Self::Compressed(encoder) => encoder.get_ref().exceeded
+        }
     }
 
-    let file_path = Path::new("logs").join(format!("{date}.log"));
-    if !file_path.exists() {
+    fn finish(self) -> std::io::Result<LogOutput> {
+        match self {
+            Self::Plain(writer) => Ok(LogOutput::File(writer.data, false)),
+            Self::Compressed(mut encoder) => match encoder.try_finish() {
+                Ok(()) => Ok(LogOutput::File(encoder.finish()?.data, true)),
+                Err(_) if encoder.get_ref().exceeded => Ok(LogOutput::TooLarge),
+                Err(why) => Err(why)
+            }
+        }
+    }
+}
+
+impl Write for Attachment {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if let Self::Plain(writer) = self {
+            if writer.data.len() + buf.len() <= writer.limit {
+                return writer.write(buf);
+            }
+            let limit = writer.limit;
+            let data = std::mem::take(&mut writer.data);
+            let mut encoder = GzEncoder::new(LimitedWriter { data: Vec::new(), limit, exceeded: false }, Compression::best());
+            encoder.write_all(&data)?;
+            *self = Self::Compressed(encoder);
+        }
+        match self {
+            Self::Compressed(encoder) => encoder.write(buf),
+            Self::Plain(_) => unreachable!()
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Plain(writer) => writer.flush(),
+            Self::Compressed(encoder) => encoder.flush()
+        }
+    }
+}
+
+pub async fn run(ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<()> {
+    let global_config = GlobalConfig::load().unwrap_or_default();
+    if !global_config.admin_user.contains(&interaction.user.id) {
         return interaction.create_interaction_response(&ctx.http, |response| {
             response.kind(InteractionResponseType::ChannelMessageWithSource)
                 .interaction_response_data(|message| {
-                    message.ephemeral(true).content("指定した日付のログファイルが見つかりませんでした。")
+                    message.ephemeral(true).content("このコマンドを実行する権限がありません。")
                 })
         }).await;
     }
 
-    let file = tokio::fs::File::open(&file_path).await?;
+    let map = interaction.data.options.iter().map(|option| {
+        (option.name.as_str(), option.resolved.as_ref().unwrap())
+    }).collect::<HashMap<_, _>>();
 
+    debug!(options = ?map, "/log");
+
+    let timezone = global_config.log.timezone().unwrap_or(chrono_tz::Japan);
+    let query = match query(&map, timezone) {
+        Ok(query) => query,
+        Err(msg) => {
+            return interaction.create_interaction_response(&ctx.http, |response| {
+                response.kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| message.ephemeral(true).content(msg))
+            }).await;
+        }
+    };
+
+    // 複数のファイルを読むと3秒以内に返信できないことがある
     interaction.create_interaction_response(&ctx.http, |response| {
-        response.kind(InteractionResponseType::ChannelMessageWithSource)
-            .interaction_response_data(|message| {
-                message
-                    .ephemeral(true)
-                    .add_file(AttachmentType::File { file: &file, filename: format!("{date}.log") })
-            })
-    }).await
+        response.kind(InteractionResponseType::DeferredChannelMessageWithSource)
+            .interaction_response_data(|message| message.ephemeral(true))
+    }).await?;
+
+    let (from, to) = (query.from.format("%Y-%m-%d").to_string(), query.to.format("%Y-%m-%d").to_string());
+    let filename = if from == to { format!("{from}.log") } else { format!("{from}_{to}.log") };
+    let limit = upload_limit(ctx, interaction);
+    let result = tokio::task::spawn_blocking(move || {
+        let mut attachment = Attachment::new(limit);
+        match query.run(&mut attachment) {
+            Ok(0) => Ok(LogOutput::Empty),
+            Ok(_) => attachment.finish(),
+            Err(_) if attachment.is_too_large() => Ok(LogOutput::TooLarge),
+            Err(why) => Err(why)
+        }
+    }).await.map_err(|_| serenity::Error::Other("Failed to read log files"))?;
+
+    let too_large = "ログが大きすぎます。期間を短くするか条件を絞り込んでください。";
+    let msg = match result {
+        Ok(LogOutput::File(data, compressed)) => {
+            let filename = if compressed { format!("{filename}.gz") } else { filename };
+            let result = interaction.create_followup_message(&ctx.http, |message| {
+                message.ephemeral(true)
+                    .add_file(AttachmentType::Bytes { data: Cow::Owned(data), filename })
+            }).await;
+            match result {
+                Ok(_) => return Ok(()),
+                // サーバーのブーストが外れるなどして上限が変わった場合
+                Err(serenity::Error::Http(why)) if why.status_code() == Some(StatusCode::PAYLOAD_TOO_LARGE) => too_large,
+                Err(why) => {
+                    error!("Failed to upload log file: {why}");
+                    "ログのアップロードに失敗しました。"
+                }
+            }
+        },
+        Ok(LogOutput::TooLarge) => too_large,
+        Ok(LogOutput::Empty) => "条件に一致するログはありませんでした。",
+        Err(why) => {
+            error!("Failed to read log files: {why}");
+            "ログファイルを読み込めませんでした。"
+        }
+    };
+    interaction.create_followup_message(&ctx.http, |message| {
+        message.ephemeral(true).content(msg)
+    }).await.map(|_| ())
 }
 
 pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
     command.name("log")
-        .description("ログファイルを出力します。")
+        .description("ログを絞り込んで出力します。")
         .create_option(|option| {
             option.name("date")
-                .description("ログの日付 (yyyy-mm-dd形式)")
+                .description("ログの日付 (yyyy-mm-dd形式)。省略した場合はfromからtoまで")
                 .kind(CommandOptionType::String)
         })
+        .create_option(|option| {
+            option.name("from")
+                .description("開始日時 (yyyy-mm-ddまたはyyyy-mm-dd hh:mm形式)。省略した場合はtoの日の0時")
+                .kind(CommandOptionType::String)
+        })
+        .create_option(|option| {
+            option.name("to")
+                .description("終了日時 (yyyy-mm-ddまたはyyyy-mm-dd hh:mm形式)。省略した場合は現在")
+                .kind(CommandOptionType::String)
+        })
+        .create_option(|option| {
+            option.name("level")
+                .description("この重要度以上のログのみ出力する")
+                .kind(CommandOptionType::String);
+            for level in LEVELS {
+                option.add_string_choice(level, level);
+            }
+            option
+        })
+        .create_option(|option| {
+            option.name("guild")
+                .description("このサーバーIDのログのみ出力する")
+                .kind(CommandOptionType::String)
+        })
+        .create_option(|option| {
+            option.name("command")
+                .description("このコマンドの実行中のログのみ出力する (例: dictionary)")
+                .kind(CommandOptionType::String)
+        })
+        .create_option(|option| {
+            option.name("tail")
+                .description("条件に一致する最後の行数")
+                .kind(CommandOptionType::Integer)
+                .min_int_value(1)
+                .max_int_value(100000)
+        })
+}
+
+#[test]
+fn test_parse_time() {
+    let timezone = chrono_tz::Japan;
+    let time = |time: Option<DateTime<Tz>>| time.map(|time| time.format("%Y-%m-%d %H:%M:%S%.3f").to_string());
+    assert_eq!(time(parse_time("2023-09-01", timezone, false)), Some("2023-09-01 00:00:00.000".into()));
+    assert_eq!(time(parse_time("2023-09-01", timezone, true)), Some("2023-09-01 23:59:59.999".into()));
+    assert_eq!(time(parse_time("2023-09-01 12:30", timezone, true)), Some("2023-09-01 12:30:59.999".into()));
+    assert_eq!(time(parse_time("2023-9-1 noon", timezone, false)), None);
+}
+
+#[test]
+fn test_attachment() {
+    let line = "2023-09-01T12:00:00.000+09:00  INFO zundamon: hello\n".repeat(10);
+    let mut attachment = Attachment::new(1000);
+    attachment.write_all(line.as_bytes()).unwrap();
+    assert!(matches!(attachment.finish().unwrap(), LogOutput::File(data, false) if data == line.as_bytes()));
+
+    let mut attachment = Attachment::new(1000);
+    for _ in 0..100 {
+        attachment.write_all(line.as_bytes()).unwrap();
+    }
+    assert!(matches!(attachment.finish().unwrap(), LogOutput::File(data, true) if data.len() <= 1000));
+
+    let mut attachment = Attachment::new(100);
+    // 圧縮したデータは書き込み中か`finish`で上限を超える
+    let result = (0..1000).try_for_each(|i| writeln!(attachment, "{i} {}", i * 7919 % 104729));
+    assert!(if result.is_err() { attachment.is_too_large() } else { matches!(attachment.finish().unwrap(), LogOutput::TooLarge) });
 }
@@ -0,0 +1,58 @@
+use crate::commands;
+use tracing::debug;
+use serenity::prelude::*;
+use serenity::Result;
+use serenity::builder::CreateApplicationCommand;
+use serenity::model::prelude::command::CommandOptionType;
+use serenity::model::application::interaction::{
+    InteractionResponseType,
+    application_command::{
+        CommandDataOptionValue,
+        ApplicationCommandInteraction
+    }
+};
+
+pub async fn run(ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<()> {
+    let options = &interaction.data.options;
+    let CommandDataOptionValue::Boolean(enable) = *options[0].resolved.as_ref().unwrap() else {
+        panic!()
+    };
+
+    debug!(enable = %enable, "/optout");
+
+    let user_id = interaction.user.id;
+
+    {
+        let guild_config = commands::guild_config(ctx, interaction).await?;
+        let mut config_lock = guild_config.write().await;
+        let opt_out_users = &mut config_lock.ignore.opt_out_users;
+        if enable {
+            opt_out_users.insert(user_id);
+        } else {
+            opt_out_users.remove(&user_id);
+        }
+    }
+
+    interaction.create_interaction_response(&ctx.http, |response| {
+        response.kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| {
+                message.ephemeral(true)
+                    .content(if enable {
+                        "あなたのメッセージを読み上げないようにしました。"
+                    } else {
+                        "あなたのメッセージを読み上げるようにしました。"
+                    })
+            })
+    }).await
+}
+
+pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command.name("optout")
+        .description("自分のメッセージの読み上げを拒否します。")
+        .create_option(|option| {
+            option.name("enable")
+                .description("読み上げを拒否する場合はTrue")
+                .kind(CommandOptionType::Boolean)
+                .required(true)
+        })
+}
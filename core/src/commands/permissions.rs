@@ -0,0 +1,211 @@
+use crate::commands::{self, SlashCommand};
+use crate::config::{self, PermissionConfig, PermissionRule};
+use std::collections::HashMap;
+use tracing::debug;
+use serenity::prelude::*;
+use serenity::builder::CreateApplicationCommand;
+use serenity::model::Permissions;
+use serenity::model::prelude::command::CommandOptionType;
+use serenity::model::application::interaction::{
+    InteractionResponseType,
+    application_command::{
+        CommandDataOptionValue,
+        ApplicationCommandInteraction
+    }
+};
+
+/// `/permissions`で選べるDiscordの権限
+const PERMISSIONS: [(&str, &str, Permissions); 5] = [
+    ("everyone", "誰でも", Permissions::empty()),
+    ("manage-guild", "サーバー管理", Permissions::MANAGE_GUILD),
+    ("manage-channels", "チャンネルの管理", Permissions::MANAGE_CHANNELS),
+    ("manage-messages", "メッセージの管理", Permissions::MANAGE_MESSAGES),
+    ("moderate-members", "メンバーをタイムアウト", Permissions::MODERATE_MEMBERS)
+];
+
+/// インタラクションのコマンド名。サブコマンドの場合は`dictionary reset`のように空白で区切る
+pub fn command_path(interaction: &ApplicationCommandInteraction) -> String {
+    match interaction.data.options.first() {
+        Some(option) if matches!(option.kind, CommandOptionType::SubCommand | CommandOptionType::SubCommandGroup) => {
+            format!("{} {}", interaction.data.name, option.name)
+        },
+        _ => interaction.data.name.clone()
+    }
+}
+
+/// インタラクションを送信したメンバーがコマンドを実行できるか判定する
+pub async fn is_permitted(ctx: &Context, command: &dyn SlashCommand, interaction: &ApplicationCommandInteraction) -> bool {
+    let (Some(guild_id), Some(member)) = (interaction.guild_id, interaction.member.as_ref()) else { return true; };
+    let permissions = member.permissions.unwrap_or_default();
+
+    // 権限の設定そのものは上書きできない
+    if command.name() == "permissions" {
+        return permissions.administrator() || permissions.contains(command.required_permissions());
+    }
+
+    let Ok(guild_config) = config::guild_config(ctx, guild_id).await else { return false; };
+    let is_allowed = guild_config.read().await.permissions
+        .is_allowed(&command_path(interaction), &member.roles, permissions, command.required_permissions());
+    is_allowed
+}
+
+fn rule_message(rule: &PermissionRule) -> String {
+    let mut allowed = PERMISSIONS.iter()
+        .filter(|(_, _, permissions)| !permissions.is_empty() && *permissions == rule.permissions)
+        .map(|(_, name, _)| name.to_string())
+        .collect::<Vec<_>>();
+    if allowed.is_empty() && !rule.permissions.is_empty() {
+        allowed.push(format!("権限 {}", rule.permissions.bits()));
+    }
+    allowed.extend(rule.roles.iter().map(|role| format!("<@&{role}>")));
+    if allowed.is_empty() {
+        "誰でも".into()
+    } else {
+        allowed.join(" / ")
+    }
+}
+
+fn list_message(config: &PermissionConfig) -> String {
+    let mut rules = config.rules.iter().collect::<Vec<_>>();
+    rules.sort_by_key(|(name, _)| name.as_str());
+    let lines = rules.into_iter()
+        .map(|(name, rule)| format!("`/{name}`: {}", rule_message(rule)))
+        .collect::<Vec<_>>();
+    if lines.is_empty() {
+        "すべてのコマンドを誰でも実行できます。".into()
+    } else {
+        lines.join("\n")
+    }
+}
+
+async fn run_inner(ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<String, String> {
+    let subcommand = &interaction.data.options[0];
+    let map = subcommand.options.iter().map(|option| {
+        (option.name.as_str(), option.resolved.as_ref().unwrap())
+    }).collect::<HashMap<_, _>>();
+
+    debug!(subcommand = %subcommand.name, options = ?map, "/permissions");
+
+    let command = match map.get("command") {
+        Some(CommandDataOptionValue::String(command)) => {
+            let command = command.trim().trim_start_matches('/').split_whitespace().collect::<Vec<_>>().join(" ");
+            let mut words = command.split(' ');
+            let exists = commands::find(words.next().unwrap_or_default()).is_some_and(|registered| {
+                match words.next() {
+                    Some(subcommand) => registered.subcommands().iter().any(|name| name == subcommand),
+                    None => true
+                }
+            });
+            if words.next().is_some() || !exists {
+                return Err(format!("`/{command}`というコマンドはありません。"));
+            }
+            Some(command)
+        },
+        _ => None
+    };
+
+    let guild_config = commands::guild_config(ctx, interaction).await.map_err(|why| why.to_string())?;
+    let mut config_lock = guild_config.write().await;
+    let permissions = &mut config_lock.permissions;
+
+    match subcommand.name.as_str() {
+        "set" => {
+            let command = command.unwrap();
+            let permission = match map.get("permission") {
+                Some(CommandDataOptionValue::String(name)) => PERMISSIONS.iter().find(|(key, _, _)| key == name).map(|(_, _, p)| *p),
+                _ => None
+            };
+            let role = match map.get("role") {
+                Some(CommandDataOptionValue::Role(role)) => Some(role.id),
+                _ => None
+            };
+            if permission.is_none() && role.is_none() {
+                return Err("権限かロールを指定してください。".into());
+            }
+            let rule = permissions.rules.entry(command.clone()).or_default();
+            if let Some(permission) = permission {
+                rule.permissions = permission;
+                if permission.is_empty() {
+                    rule.roles.clear();
+                }
+            }
+            if let Some(role) = role {
+                rule.roles.insert(role);
+            }
+            Ok(format!("`/{command}`: {}", rule_message(rule)))
+        },
+        "reset" => {
+            let command = command.unwrap();
+            match PermissionConfig::default_rules().into_iter().find(|(name, _)| *name == command) {
+                Some((_, rule)) => {
+                    permissions.rules.insert(command.clone(), rule);
+                },
+                None => {
+                    permissions.rules.remove(&command);
+                }
+            }
+            Ok(format!("`/{command}`の権限を初期設定に戻しました。\n{}", list_message(permissions)))
+        },
+        "list" => Ok(list_message(permissions)),
+        _ => panic!("unexpected subcommand name")
+    }
+}
+
+pub async fn run(ctx: &Context, interaction: &ApplicationCommandInteraction) -> serenity::Result<()> {
+    let msg = run_inner(ctx, interaction).await;
+    interaction.create_interaction_response(&ctx.http, |response| {
+        response.kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| {
+                match msg {
+                    Ok(msg) => message.content(msg).allowed_mentions(|m| m.empty_parse()),
+                    Err(msg) => message.ephemeral(true).content(msg)
+                }
+            })
+    }).await
+}
+
+pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command.name("permissions")
+        .description("コマンドを実行できるメンバーを設定します。")
+        .create_option(|option| {
+            option.name("set")
+                .description("コマンドを実行できる権限かロールを設定します。")
+                .kind(CommandOptionType::SubCommand)
+                .create_sub_option(|option| {
+                    option.name("command")
+                        .description("コマンド名 (例: dictionary reset)")
+                        .kind(CommandOptionType::String)
+                        .required(true)
+                })
+                .create_sub_option(|option| {
+                    let option = option.name("permission")
+                        .description("実行に必要な権限")
+                        .kind(CommandOptionType::String);
+                    for (value, name, _) in PERMISSIONS {
+                        option.add_string_choice(name, value);
+                    }
+                    option
+                })
+                .create_sub_option(|option| {
+                    option.name("role")
+                        .description("実行を許可するロール")
+                        .kind(CommandOptionType::Role)
+                })
+        })
+        .create_option(|option| {
+            option.name("reset")
+                .description("コマンドの権限を初期設定に戻します。")
+                .kind(CommandOptionType::SubCommand)
+                .create_sub_option(|option| {
+                    option.name("command")
+                        .description("コマンド名 (例: dictionary reset)")
+                        .kind(CommandOptionType::String)
+                        .required(true)
+                })
+        })
+        .create_option(|option| {
+            option.name("list")
+                .description("コマンドの権限の設定を表示します。")
+                .kind(CommandOptionType::SubCommand)
+        })
+}
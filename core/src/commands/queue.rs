@@ -0,0 +1,59 @@
+use crate::type_map::TrackInfo;
+use tracing::debug;
+use serenity::Result;
+use serenity::prelude::*;
+use serenity::builder::CreateApplicationCommand;
+use serenity::model::application::interaction::{
+    InteractionResponseType,
+    application_command::ApplicationCommandInteraction
+};
+
+/// 一覧に表示する文章の最大文字数
+const EXCERPT_LEN: usize = 30;
+/// 一覧に表示する最大件数
+const MAX_ITEMS: usize = 20;
+
+pub async fn run(ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<()> {
+    debug!("/queue");
+
+    let manager = songbird::get(ctx).await.unwrap();
+    let tracks = match manager.get(interaction.guild_id.unwrap()) {
+        Some(handle) => handle.lock().await.queue().current_queue(),
+        None => Vec::new()
+    };
+
+    let mut lines = Vec::new();
+    for (i, track) in tracks.iter().enumerate().take(MAX_ITEMS) {
+        let info = track.typemap().read().await.get::<TrackInfo>().cloned().unwrap_or_default();
+        let mut excerpt = info.text.chars().take(EXCERPT_LEN).collect::<String>();
+        if info.text.chars().count() > EXCERPT_LEN {
+            excerpt.push('…');
+        }
+        let author = info.author.map_or("システム".to_string(), |id| format!("<@{id}>"));
+        let seconds = track.metadata().duration.map_or(0, |d| d.as_secs());
+        let index = if i == 0 {"再生中".to_string()} else {format!("{i}.")};
+        lines.push(format!("{index} {author} `{excerpt}` ({seconds}秒)"));
+    }
+    if tracks.len() > MAX_ITEMS {
+        lines.push(format!("ほか{}件", tracks.len() - MAX_ITEMS));
+    }
+
+    let msg = if lines.is_empty() {
+        "読み上げ待ちのメッセージはありません。".to_string()
+    } else {
+        lines.join("\n")
+    };
+
+    interaction.create_interaction_response(&ctx.http, |response| {
+        response.kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| {
+                message.ephemeral(true)
+                    .allowed_mentions(|m| m.empty_parse())
+                    .content(msg)
+            })
+    }).await
+}
+
+pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command.name("queue").description("読み上げ待ちのメッセージを表示します。")
+}
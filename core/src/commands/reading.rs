@@ -0,0 +1,216 @@
+use crate::commands;
+use crate::config::{ReadingConfig, LengthConfig, TruncatePolicy};
+use std::collections::HashMap;
+use tracing::debug;
+use serenity::prelude::*;
+use serenity::Result;
+use serenity::builder::CreateApplicationCommand;
+use serenity::model::prelude::command::CommandOptionType;
+use serenity::model::application::interaction::{
+    InteractionResponseType,
+    application_command::{
+        CommandDataOptionValue,
+        ApplicationCommandInteraction
+    }
+};
+
+fn on_off(enable: bool) -> &'static str {
+    if enable {"有効"} else {"無効"}
+}
+
+fn content_message(config: &ReadingConfig) -> String {
+    format!(
+        "添付ファイル: {}\n画像の説明文: {}\nスタンプ: {}\n埋め込み: {}",
+        on_off(config.attachment),
+        on_off(config.alt_text),
+        on_off(config.sticker),
+        on_off(config.embed)
+    )
+}
+
+const POLICIES: [(&str, &str, TruncatePolicy); 4] = [
+    ("最大文字数で切る", "truncate", TruncatePolicy::Truncate),
+    ("文の区切りで切る", "sentence", TruncatePolicy::Sentence),
+    ("「長文のため省略」と読み上げる", "summarize", TruncatePolicy::Summarize),
+    ("先頭の数文のみ読み上げる", "first_sentences", TruncatePolicy::FirstSentences)
+];
+
+fn length_message(config: &LengthConfig) -> String {
+    let policy = POLICIES.iter().find(|(_, _, policy)| *policy == config.policy).unwrap().0;
+    format!(
+        "最大文字数: {}\n省略方法: {}\n読み上げる文の数: {}\n読み上げ待ちの上限: {}",
+        config.max_len,
+        policy,
+        config.sentences,
+        if config.max_queue_secs == 0 {"無制限".to_string()} else {format!("{}秒", config.max_queue_secs)}
+    )
+}
+
+fn events_message(config: &ReadingConfig) -> String {
+    format!(
+        "メッセージの編集: {}\n削除されたメッセージの読み上げ取り消し: {}\nリアクション: {}",
+        on_off(config.edit),
+        on_off(config.cancel_deleted),
+        on_off(config.reaction)
+    )
+}
+
+pub async fn run(ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<()> {
+    let subcommand = &interaction.data.options[0];
+    let map = subcommand.options.iter().map(|option| {
+        (option.name.as_str(), option.resolved.as_ref().unwrap())
+    }).collect::<HashMap<_, _>>();
+    let get_bool = |name: &str| match map.get(name) {
+        Some(CommandDataOptionValue::Boolean(value)) => Some(*value),
+        _ => None
+    };
+    let get_integer = |name: &str| match map.get(name) {
+        Some(CommandDataOptionValue::Integer(value)) => Some(*value),
+        _ => None
+    };
+
+    debug!(subcommand = %subcommand.name, options = ?map, "/reading");
+
+    let msg = {
+        let guild_config = commands::guild_config(ctx, interaction).await?;
+        let mut config_lock = guild_config.write().await;
+        let config = &mut *config_lock;
+        let reading = &mut config.reading;
+        match subcommand.name.as_str() {
+            "events" => {
+                if let Some(edit) = get_bool("edit") {
+                    reading.edit = edit;
+                }
+                if let Some(delete) = get_bool("delete") {
+                    reading.cancel_deleted = delete;
+                }
+                if let Some(reaction) = get_bool("reaction") {
+                    reading.reaction = reaction;
+                }
+                events_message(reading)
+            },
+            "content" => {
+                if let Some(attachment) = get_bool("attachment") {
+                    reading.attachment = attachment;
+                }
+                if let Some(alt_text) = get_bool("alt-text") {
+                    reading.alt_text = alt_text;
+                }
+                if let Some(sticker) = get_bool("sticker") {
+                    reading.sticker = sticker;
+                }
+                if let Some(embed) = get_bool("embed") {
+                    reading.embed = embed;
+                }
+                content_message(reading)
+            },
+            "length" => {
+                let length = &mut config.length;
+                if let Some(max) = get_integer("max") {
+                    length.max_len = max as usize;
+                }
+                if let Some(CommandDataOptionValue::String(policy)) = map.get("policy") {
+                    length.policy = POLICIES.iter().find(|(_, name, _)| name == policy).unwrap().2;
+                }
+                if let Some(sentences) = get_integer("sentences") {
+                    length.sentences = sentences as usize;
+                }
+                if let Some(queue) = get_integer("queue") {
+                    length.max_queue_secs = queue as u64;
+                }
+                length_message(length)
+            },
+            _ => panic!("unexpected subcommand name")
+        }
+    };
+
+    interaction.create_interaction_response(&ctx.http, |response| {
+        response.kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| message.content(msg))
+    }).await
+}
+
+pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command.name("reading")
+        .description("読み上げる内容を設定します。")
+        .create_option(|option| {
+            option.name("events")
+                .description("メッセージの投稿以外に読み上げるイベントを設定します。")
+                .kind(CommandOptionType::SubCommand)
+                .create_sub_option(|option| {
+                    option.name("edit")
+                        .kind(CommandOptionType::Boolean)
+                        .description("編集されたメッセージを読み上げます。")
+                })
+                .create_sub_option(|option| {
+                    option.name("delete")
+                        .kind(CommandOptionType::Boolean)
+                        .description("読み上げ前に削除されたメッセージの読み上げを取り消します。")
+                })
+                .create_sub_option(|option| {
+                    option.name("reaction")
+                        .kind(CommandOptionType::Boolean)
+                        .description("リアクションを読み上げます。")
+                })
+        })
+        .create_option(|option| {
+            option.name("content")
+                .description("本文以外に読み上げる内容を設定します。")
+                .kind(CommandOptionType::SubCommand)
+                .create_sub_option(|option| {
+                    option.name("attachment")
+                        .kind(CommandOptionType::Boolean)
+                        .description("添付ファイルの種類と数を読み上げます。")
+                })
+                .create_sub_option(|option| {
+                    option.name("alt-text")
+                        .kind(CommandOptionType::Boolean)
+                        .description("画像の説明文を読み上げます。")
+                })
+                .create_sub_option(|option| {
+                    option.name("sticker")
+                        .kind(CommandOptionType::Boolean)
+                        .description("スタンプの名前を読み上げます。")
+                })
+                .create_sub_option(|option| {
+                    option.name("embed")
+                        .kind(CommandOptionType::Boolean)
+                        .description("埋め込みのタイトルを読み上げます。")
+                })
+        })
+        .create_option(|option| {
+            option.name("length")
+                .description("長文の読み上げ方を設定します。")
+                .kind(CommandOptionType::SubCommand)
+                .create_sub_option(|option| {
+                    option.name("max")
+                        .kind(CommandOptionType::Integer)
+                        .min_int_value(1)
+                        .max_int_value(2000)
+                        .description("読み上げる最大文字数")
+                })
+                .create_sub_option(|option| {
+                    option.name("policy")
+                        .kind(CommandOptionType::String)
+                        .description("最大文字数を超えたときの省略方法");
+                    for (name, value, _) in POLICIES {
+                        option.add_string_choice(name, value);
+                    }
+                    option
+                })
+                .create_sub_option(|option| {
+                    option.name("sentences")
+                        .kind(CommandOptionType::Integer)
+                        .min_int_value(1)
+                        .max_int_value(20)
+                        .description("「先頭の数文のみ読み上げる」で読み上げる文の数")
+                })
+                .create_sub_option(|option| {
+                    option.name("queue")
+                        .kind(CommandOptionType::Integer)
+                        .min_int_value(0)
+                        .max_int_value(3600)
+                        .description("読み上げ待ちの音声の合計の長さの上限(秒)。0で無制限")
+                })
+        })
+}
@@ -0,0 +1,37 @@
+use tracing::debug;
+use serenity::Result;
+use serenity::prelude::*;
+use serenity::builder::CreateApplicationCommand;
+use serenity::model::application::interaction::{
+    InteractionResponseType,
+    application_command::ApplicationCommandInteraction
+};
+
+pub async fn run(ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<()> {
+    debug!("/resume");
+
+    let manager = songbird::get(ctx).await.unwrap();
+
+    let success = if let Some(handle) = manager.get(interaction.guild_id.unwrap()) {
+        let handler = handle.lock().await;
+        handler.queue().resume().is_ok()
+    } else {
+        false
+    };
+
+    interaction.create_interaction_response(&ctx.http, |response| {
+        response.kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|message| {
+                let msg = if success {
+                    "読み上げを再開しました。"
+                } else {
+                    "読み上げの再開に失敗しました。"
+                };
+                message.ephemeral(!success).content(msg)
+            })
+    }).await
+}
+
+pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    command.name("resume").description("一時停止した読み上げを再開します。")
+}
@@ -2,21 +2,34 @@ use tracing::debug;
 use serenity::Result;
 use serenity::prelude::*;
 use serenity::builder::CreateApplicationCommand;
+use serenity::model::prelude::command::CommandOptionType;
 use serenity::model::application::interaction::{
     InteractionResponseType,
-    application_command::ApplicationCommandInteraction
+    application_command::{
+        CommandDataOptionValue,
+        ApplicationCommandInteraction
+    }
 };
 
 pub async fn run(ctx: &Context, interaction: &ApplicationCommandInteraction) -> Result<()> {
-    debug!("/skip");
-    
+    let all = matches!(
+        interaction.data.options.first().and_then(|opt| opt.resolved.as_ref()),
+        Some(CommandDataOptionValue::Boolean(true))
+    );
+
+    debug!(all = %all, "/skip");
+
     let manager = songbird::get(ctx).await.unwrap();
 
     let success = if let Some(handle) = manager.get(interaction.guild_id.unwrap()) {
         let mut handler = handle.lock().await;
-        handler.stop();
-        handler.queue().modify_queue(|q| q.clear());
-        true
+        if all {
+            handler.stop();
+            handler.queue().modify_queue(|q| q.clear());
+            true
+        } else {
+            handler.queue().skip().is_ok()
+        }
     } else {
         false
     };
@@ -24,10 +37,10 @@ pub async fn run(ctx: &Context, interaction: &ApplicationCommandInteraction) ->
     interaction.create_interaction_response(&ctx.http, |response| {
         response.kind(InteractionResponseType::ChannelMessageWithSource)
             .interaction_response_data(|message| {
-                let msg = if success {
-                    "読み上げをスキップしました。"
-                } else {
-                    "読み上げのスキップに失敗しました。"
+                let msg = match (success, all) {
+                    (true, true) => "すべての読み上げをスキップしました。",
+                    (true, false) => "読み上げをスキップしました。",
+                    (false, _) => "読み上げのスキップに失敗しました。"
                 };
                 message.ephemeral(!success).content(msg)
             })
@@ -35,5 +48,11 @@ pub async fn run(ctx: &Context, interaction: &ApplicationCommandInteraction) ->
 }
 
 pub fn register(command: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
-    command.name("skip").description("現在の読み上げをスキップします。")
+    command.name("skip")
+        .description("現在の読み上げをスキップします。")
+        .create_option(|option| {
+            option.name("all")
+                .description("読み上げ待ちのメッセージもすべてスキップする場合はTrue")
+                .kind(CommandOptionType::Boolean)
+        })
 }
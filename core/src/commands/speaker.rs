@@ -1,4 +1,4 @@
-use crate::ConfigData;
+use crate::commands;
 use std::collections::BTreeMap;
 use tracing::debug;
 use once_cell::sync::Lazy;
@@ -102,13 +102,10 @@ pub async fn run(ctx: &Context, interaction: &ApplicationCommandInteraction) ->
 
     debug!(speaker_id = %speaker_id, "/speaker");
 
-    let guild_id = interaction.guild_id.unwrap();
-
     {
-        let data_read = ctx.data.read().await;
-        let config = data_read.get::<ConfigData>().unwrap();
-        let mut config_lock = config.lock().unwrap();
-        let speaker = &mut config_lock.guild_config_mut(guild_id).speaker_id;
+        let guild_config = commands::guild_config(ctx, interaction).await?;
+        let mut config_lock = guild_config.write().await;
+        let speaker = &mut config_lock.voice.speaker_id;
         *speaker = speaker_id;
     }
 
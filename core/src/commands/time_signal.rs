@@ -1,4 +1,4 @@
-use crate::ConfigData;
+use crate::commands;
 use tracing::debug;
 use serenity::prelude::*;
 use serenity::Result;
@@ -20,13 +20,10 @@ pub async fn run(ctx: &Context, interaction: &ApplicationCommandInteraction) ->
 
     debug!(enable = %enable, "/time-signal");
 
-    let guild_id = interaction.guild_id.unwrap();
-
     {
-        let data_read = ctx.data.read().await;
-        let config = data_read.get::<ConfigData>().expect("Expected ConfigData in TypeMap.");
-        let mut config_lock = config.lock().unwrap();
-        let time_signal = &mut config_lock.guild_config_mut(guild_id).time_signal;
+        let guild_config = commands::guild_config(ctx, interaction).await?;
+        let mut config_lock = guild_config.write().await;
+        let time_signal = &mut config_lock.time_signal;
         *time_signal = enable;
     }
 
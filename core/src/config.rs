@@ -1,135 +1,795 @@
+use crate::storage;
+use crate::type_map::ConfigData;
 use dictionary::Dictionary;
-use std::io::Write;
-use std::path::Path;
-use std::collections::HashMap;
-use serenity::model::prelude::{GuildId, UserId};
+use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use tokio::sync::{Mutex, RwLock};
+use serenity::prelude::Context;
+use serenity::model::prelude::{GuildId, UserId, RoleId, ChannelId};
+use serenity::model::Permissions;
 use serde::{Serialize, Deserialize};
-use anyhow::Result;
+use anyhow::{Context as _, Result};
+use tracing::error;
 
 pub const CONFIG_DIR: &str = "config";
 pub const CONFIG_FILE: &str = "config.json";
 pub const DICT_FILE: &str = "dictionary.json";
 pub const GLOBAL_CONFIG_FILE: &str = "global_config.json";
 
+/// 現在の`GuildConfig`の形式のバージョン
+pub const CONFIG_VERSION: u32 = 1;
+
+/// バージョン`i`から`i + 1`への移行を`i`番目に並べる
+const MIGRATIONS: [fn(&mut serde_json::Value); CONFIG_VERSION as usize] = [
+    // v1: `speaker_id`を`voice.speaker_id`に移動
+    |config| {
+        if let Some(speaker_id) = config.as_object_mut().and_then(|config| config.remove("speaker_id")) {
+            config["voice"] = serde_json::json!({ "speaker_id": speaker_id });
+        }
+    }
+];
+
 // デフォルトはノーマルずんだもん
 fn default_speaker() -> u32 { 3 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct GlobalConfig {
-    pub admin_user: Vec<UserId>
+    pub admin_user: Vec<UserId>,
+    /// スラッシュコマンドをギルドごとではなくグローバルに登録する
+    #[serde(default)]
+    pub global_commands: bool,
+    /// ログの出力先と形式。起動時に読み込む
+    #[serde(default)]
+    pub log: LogConfig
 }
 
 impl GlobalConfig {
     pub fn load() -> Result<Self> {
-        let dir = Path::new(CONFIG_DIR);
-        let config_path = dir.join(GLOBAL_CONFIG_FILE);
-        if !config_path.exists() {
-            std::fs::create_dir_all(dir)?;
-            let mut file = std::fs::File::create(&config_path)?;
-            let config = GlobalConfig::default();
-            writeln!(file, "{}", serde_json::to_string_pretty(&config)?)?;
-        }
-        let config = std::fs::read_to_string(&config_path)?;
-        Ok(serde_json::from_str(&config)?)
+        storage::get().load_global()
     }
 
     pub fn save(&self) -> Result<()> {
-        let path = Path::new(CONFIG_DIR).join(GLOBAL_CONFIG_FILE);
-        let mut file = std::fs::File::create(path)?;
-        writeln!(file, "{}", serde_json::to_string_pretty(&self)?)?;
-        Ok(())
+        storage::get().save_global(self)
+    }
+
+    /// 追加した場合は`true`を返す
+    pub fn add_admin(&mut self, user_id: UserId) -> bool {
+        if self.admin_user.contains(&user_id) {
+            return false;
+        }
+        self.admin_user.push(user_id);
+        true
+    }
+
+    /// 削除した場合は`true`を返す
+    pub fn remove_admin(&mut self, user_id: UserId) -> bool {
+        let Some(index) = self.admin_user.iter().position(|&user| user == user_id) else {
+            return false;
+        };
+        self.admin_user.swap_remove(index);
+        true
+    }
+
+    /// 手動で編集された設定を検証して読み込む
+    pub fn reload() -> Result<Self> {
+        storage::get().reload_global()
     }
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
-pub struct Config(HashMap<GuildId, GuildConfig>);
+/// ログの出力の設定
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+    pub format: LogFormat,
+    /// 時刻とファイル名の日付のタイムゾーン(`Asia/Tokyo`など)
+    pub timezone: String,
+    /// `logs/`のファイルに出力する
+    pub file: bool,
+    /// 標準エラー出力にも出力する
+    pub stderr: bool,
+    /// ファイルがこのサイズ(MB)を超えたら日付が変わる前でも次のファイルに切り替える。0の場合は無制限
+    pub max_file_mb: u64,
+    /// この日数より前のファイルを削除する。0の場合は削除しない
+    pub retention_days: u32,
+    /// ファイルの合計サイズ(MB)の上限。超えた分は古いファイルから削除する。0の場合は無制限
+    pub max_total_mb: u64,
+    /// 書き込みが終わったファイルをgzipで圧縮する
+    pub compress: bool
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// 複数行の読みやすい形式
+    Pretty,
+    /// 1行ずつの簡潔な形式
+    Compact,
+    /// 1行ずつのJSON。`/log`の絞り込みはこの形式が最も正確
+    Json
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::Json,
+            timezone: "Asia/Tokyo".into(),
+            file: true,
+            stderr: false,
+            max_file_mb: 10,
+            retention_days: 30,
+            max_total_mb: 0,
+            compress: true
+        }
+    }
+}
+
+impl LogConfig {
+    pub fn timezone(&self) -> Result<chrono_tz::Tz> {
+        self.timezone.parse().map_err(|_| anyhow::anyhow!("unknown timezone: {}", self.timezone))
+    }
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "pretty" => Ok(Self::Pretty),
+            "compact" => Ok(Self::Compact),
+            "json" => Ok(Self::Json),
+            _ => anyhow::bail!("unknown log format: {s} (expected pretty, compact or json)")
+        }
+    }
+}
+
+/// `Config::reload`の結果
+#[derive(Debug, Default)]
+pub struct ReloadReport {
+    pub reloaded: Vec<GuildId>,
+    /// 読み込めなかったギルドとその理由。これらのギルドは読み込む前の設定のまま
+    pub errors: Vec<(GuildId, anyhow::Error)>
+}
+
+/// すべてのギルドの設定
+///
+/// ギルドごとに`RwLock`で保護し、必要になったときに読み込む。
+#[derive(Debug, Default)]
+pub struct Config {
+    guilds: std::sync::RwLock<HashMap<GuildId, Arc<RwLock<GuildConfig>>>>,
+    /// 同じファイルに同時に書き込まないようにする
+    saving: Mutex<()>
+}
 
 #[non_exhaustive]
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GuildConfig {
+    /// 設定ファイルの形式のバージョン
+    #[serde(default)]
+    pub version: u32,
     pub time_signal: bool,
-    #[serde(default = "default_speaker")]
-    pub speaker_id: u32,
+    #[serde(default)]
+    pub voice: VoiceConfig,
+    #[serde(default)]
+    pub ignore: IgnoreConfig,
+    #[serde(default)]
+    pub reading: ReadingConfig,
+    #[serde(default)]
+    pub voice_announce: VoiceAnnounceConfig,
+    #[serde(default)]
+    pub length: LengthConfig,
+    #[serde(default)]
+    pub flood: FloodConfig,
+    #[serde(default)]
+    pub auto_join: AutoJoinConfig,
+    #[serde(default)]
+    pub auto_disconnect: AutoDisconnectConfig,
+    #[serde(default)]
+    pub permissions: PermissionConfig,
+    #[serde(default)]
+    pub commands: CommandConfig,
     #[serde(skip)]
     pub dictionary: Dictionary
 }
 
+impl Default for GuildConfig {
+    fn default() -> Self {
+        Self {
+            version: CONFIG_VERSION,
+            time_signal: false,
+            voice: VoiceConfig::default(),
+            ignore: IgnoreConfig::default(),
+            reading: ReadingConfig::default(),
+            voice_announce: VoiceAnnounceConfig::default(),
+            length: LengthConfig::default(),
+            flood: FloodConfig::default(),
+            auto_join: AutoJoinConfig::default(),
+            auto_disconnect: AutoDisconnectConfig::default(),
+            permissions: PermissionConfig::default(),
+            commands: CommandConfig::default(),
+            dictionary: Dictionary::default()
+        }
+    }
+}
+
+/// 読み上げる声の設定
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VoiceConfig {
+    pub speaker_id: u32
+}
+
+impl Default for VoiceConfig {
+    fn default() -> Self {
+        Self { speaker_id: default_speaker() }
+    }
+}
+
+/// 読み上げ対象から除外するメッセージの設定
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IgnoreConfig {
+    /// モデレーターが指定した読み上げないユーザー
+    pub users: HashSet<UserId>,
+    /// 自身のメッセージの読み上げを拒否したユーザー
+    pub opt_out_users: HashSet<UserId>,
+    /// 読み上げないロール
+    pub roles: HashSet<RoleId>,
+    /// これらの文字列で始まるメッセージは読み上げない。`/ignore`で追加する
+    pub prefixes: Vec<String>
+}
+
+impl IgnoreConfig {
+    pub fn is_ignored(&self, user: UserId, roles: &[RoleId], content: &str) -> bool {
+        self.users.contains(&user)
+            || self.opt_out_users.contains(&user)
+            || roles.iter().any(|role| self.roles.contains(role))
+            || self.prefixes.iter().any(|prefix| content.starts_with(prefix.as_str()))
+    }
+}
+
+/// 読み上げる内容の設定
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReadingConfig {
+    /// 編集されたメッセージを読み上げる
+    pub edit: bool,
+    /// 読み上げ前に削除されたメッセージの読み上げを取り消す
+    pub cancel_deleted: bool,
+    /// リアクションを読み上げる
+    pub reaction: bool,
+    /// 添付ファイルの種類と数を読み上げる
+    pub attachment: bool,
+    /// 画像の説明文(代替テキスト)を読み上げる
+    pub alt_text: bool,
+    /// スタンプの名前を読み上げる
+    pub sticker: bool,
+    /// 埋め込みのタイトルを読み上げる
+    pub embed: bool
+}
+
+impl Default for ReadingConfig {
+    fn default() -> Self {
+        Self {
+            edit: false,
+            cancel_deleted: true,
+            reaction: false,
+            attachment: true,
+            alt_text: true,
+            sticker: true,
+            embed: false
+        }
+    }
+}
+
+/// 長文の読み上げ方の設定
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LengthConfig {
+    /// 読み上げる最大文字数
+    pub max_len: usize,
+    /// 最大文字数を超えたときの省略方法
+    pub policy: TruncatePolicy,
+    /// `TruncatePolicy::FirstSentences`で読み上げる文の数
+    pub sentences: usize,
+    /// 読み上げ待ちの音声の合計の長さの上限(秒)。0の場合は無制限
+    pub max_queue_secs: u64
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TruncatePolicy {
+    /// 最大文字数で切る
+    Truncate,
+    /// 最大文字数以内の最後の文の区切りで切る
+    Sentence,
+    /// 「長文のため省略」とだけ読み上げる
+    Summarize,
+    /// 先頭から`sentences`個の文を読み上げる
+    FirstSentences
+}
+
+impl Default for LengthConfig {
+    fn default() -> Self {
+        Self {
+            max_len: 255,
+            policy: TruncatePolicy::Sentence,
+            sentences: 3,
+            max_queue_secs: 0
+        }
+    }
+}
+
+impl LengthConfig {
+    /// 文の区切りとみなす文字
+    const SENTENCE_END: [char; 6] = ['。', '！', '？', '!', '?', '．'];
+
+    /// 設定に従って長文を省略する
+    pub fn apply(&self, text: &str) -> String {
+        let len = text.chars().count();
+        match self.policy {
+            _ if len <= self.max_len => text.to_string(),
+            TruncatePolicy::Truncate => {
+                format!("{} 以下省略", text.chars().take(self.max_len).collect::<String>())
+            },
+            TruncatePolicy::Sentence => {
+                let head = text.chars().take(self.max_len).collect::<String>();
+                let end = head.rfind(Self::SENTENCE_END)
+                    .map(|i| i + head[i..].chars().next().unwrap().len_utf8())
+                    .or_else(|| head.rfind(char::is_whitespace))
+                    .unwrap_or(head.len());
+                format!("{} 以下省略", &head[..end])
+            },
+            TruncatePolicy::Summarize => "長文のため省略".to_string(),
+            TruncatePolicy::FirstSentences => {
+                let end = text.match_indices(Self::SENTENCE_END)
+                    .nth(self.sentences.max(1) - 1)
+                    .map_or(text.len(), |(i, s)| i + s.len());
+                let head = &text[..end];
+                if head.chars().count() > self.max_len {
+                    Self { policy: TruncatePolicy::Sentence, ..self.clone() }.apply(head)
+                } else if end < text.len() {
+                    format!("{head} 以下省略")
+                } else {
+                    head.to_string()
+                }
+            }
+        }
+    }
+}
+
+/// 大量のメッセージで読み上げが滞らないようにする設定
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FloodConfig {
+    /// 読み上げ待ちにできるメッセージの最大数。0の場合は無制限
+    pub max_pending: usize,
+    /// ユーザーごとに1分間に読み上げるメッセージの最大数。0の場合は無制限
+    pub max_per_minute: u32,
+    /// 同じユーザーの連続したメッセージをまとめて読み上げる
+    pub coalesce: bool,
+    /// 読み上げ待ちが上限を超えたときに古いものから破棄する。
+    /// `false`の場合は新しいメッセージを読み上げない
+    pub drop_oldest: bool
+}
+
+/// ボイスチャンネルへの自動接続の設定
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutoJoinConfig {
+    pub rules: Vec<AutoJoinRule>,
+    /// 起動時に前回接続していたボイスチャンネルに再接続する
+    pub rejoin_on_startup: bool
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AutoJoinRule {
+    /// 入室を監視するボイスチャンネル
+    pub voice_channel: ChannelId,
+    /// 接続したときに読み上げるテキストチャンネル
+    pub text_channel: ChannelId,
+    /// 指定したユーザーが入室したときのみ接続する。空の場合は誰が入室しても接続する
+    pub users: Vec<UserId>
+}
+
+impl Default for AutoJoinConfig {
+    fn default() -> Self {
+        Self {
+            rules: Vec::new(),
+            rejoin_on_startup: true
+        }
+    }
+}
+
+impl AutoJoinConfig {
+    /// ユーザーが入室したときに接続するルールを返す
+    pub fn find(&self, voice_channel: ChannelId, user: UserId) -> Option<&AutoJoinRule> {
+        self.rules.iter().find(|rule| {
+            rule.voice_channel == voice_channel && (rule.users.is_empty() || rule.users.contains(&user))
+        })
+    }
+}
+
+/// ボイスチャンネルからの自動切断の設定
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutoDisconnectConfig {
+    /// ボイスチャンネルからbot以外のメンバーがいなくなってから切断するまでの秒数
+    pub grace_secs: u64,
+    /// メッセージが読み上げられないまま経過したら切断する分数。0の場合は切断しない
+    pub idle_minutes: u64,
+    /// 切断する前にお知らせを読み上げる
+    pub announce: bool,
+    /// 切断する前に読み上げるお知らせ
+    pub message: String,
+    /// 自動で切断しない (24時間モード)
+    pub stay: bool
+}
+
+impl Default for AutoDisconnectConfig {
+    fn default() -> Self {
+        Self {
+            grace_secs: 0,
+            idle_minutes: 0,
+            announce: false,
+            message: Self::DEFAULT_MESSAGE.into(),
+            stay: false
+        }
+    }
+}
+
+impl AutoDisconnectConfig {
+    pub const DEFAULT_MESSAGE: &'static str = "読み上げを終了するのだ";
+}
+
+/// スラッシュコマンドの設定
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CommandConfig {
+    /// 無効にしたコマンドの名前
+    pub disabled: HashSet<String>
+}
+
+/// コマンドを実行できるメンバーの設定
+///
+/// キーは`dictionary`のようなコマンド名か、`dictionary reset`のようなサブコマンドを含む名前。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PermissionConfig {
+    pub rules: HashMap<String, PermissionRule>
+}
+
+/// 指定したロールのいずれかを持つか、指定した権限をすべて持つメンバーのみ実行できる。
+/// どちらも空の場合は誰でも実行できる
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PermissionRule {
+    pub roles: HashSet<RoleId>,
+    pub permissions: Permissions
+}
+
+impl Default for PermissionConfig {
+    fn default() -> Self {
+        Self {
+            rules: Self::default_rules().into_iter().map(|(name, rule)| (name.to_string(), rule)).collect()
+        }
+    }
+}
+
+impl PermissionConfig {
+    /// 設定を壊しうるコマンドはデフォルトでサーバー管理権限を必要とする
+    pub fn default_rules() -> Vec<(&'static str, PermissionRule)> {
+        ["dictionary reset", "dictionary import", "speaker", "time-signal"].into_iter()
+            .map(|name| (name, PermissionRule { roles: HashSet::new(), permissions: Permissions::MANAGE_GUILD }))
+            .collect()
+    }
+
+    /// コマンドに適用されるルールを返す。サブコマンドのルールがあればそちらを優先する
+    pub fn rule(&self, command: &str) -> Option<&PermissionRule> {
+        self.rules.get(command).or_else(|| {
+            let name = command.split_whitespace().next()?;
+            self.rules.get(name)
+        })
+    }
+
+    /// メンバーがコマンドを実行できるか判定する
+    ///
+    /// ルールがない場合は`default`の権限をすべて持つメンバーのみ実行できる。
+    /// 管理者権限を持つメンバーは常に実行できる。
+    pub fn is_allowed(&self, command: &str, roles: &[RoleId], permissions: Permissions, default: Permissions) -> bool {
+        if permissions.administrator() {
+            return true;
+        }
+        match self.rule(command) {
+            Some(rule) => rule.is_allowed(roles, permissions),
+            None => permissions.contains(default)
+        }
+    }
+}
+
+impl PermissionRule {
+    pub fn is_allowed(&self, roles: &[RoleId], permissions: Permissions) -> bool {
+        if self.roles.is_empty() && self.permissions.is_empty() {
+            return true;
+        }
+        roles.iter().any(|role| self.roles.contains(role))
+            || (!self.permissions.is_empty() && permissions.contains(self.permissions))
+    }
+}
+
+/// ボイスチャンネルへの入退室などを読み上げる設定
+///
+/// テンプレート中の`{name}`はメンバーの名前に、`{channel}`は移動先のチャンネル名に置き換えられる。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VoiceAnnounceConfig {
+    pub join: Announcement,
+    pub leave: Announcement,
+    pub stream: Announcement,
+    #[serde(rename = "move")]
+    pub move_channel: Announcement
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Announcement {
+    pub enabled: bool,
+    pub template: String
+}
+
+impl Announcement {
+    fn new(template: &str) -> Self {
+        Self { enabled: false, template: template.into() }
+    }
+
+    pub fn render(&self, name: &str, channel: &str) -> String {
+        self.template.replace("{name}", name).replace("{channel}", channel)
+    }
+}
+
+impl Default for VoiceAnnounceConfig {
+    fn default() -> Self {
+        Self {
+            join: Announcement::new(VoiceAnnounceConfig::DEFAULT_JOIN),
+            leave: Announcement::new(VoiceAnnounceConfig::DEFAULT_LEAVE),
+            stream: Announcement::new(VoiceAnnounceConfig::DEFAULT_STREAM),
+            move_channel: Announcement::new(VoiceAnnounceConfig::DEFAULT_MOVE)
+        }
+    }
+}
+
+impl VoiceAnnounceConfig {
+    pub const DEFAULT_JOIN: &'static str = "{name}さんが入室しました";
+    pub const DEFAULT_LEAVE: &'static str = "{name}さんが退室しました";
+    pub const DEFAULT_STREAM: &'static str = "{name}さんが配信を開始しました";
+    pub const DEFAULT_MOVE: &'static str = "{name}さんが{channel}に移動しました";
+
+    /// イベント名に対応する設定を返す
+    pub fn get_mut(&mut self, event: &str) -> Option<&mut Announcement> {
+        match event {
+            "join" => Some(&mut self.join),
+            "leave" => Some(&mut self.leave),
+            "stream" => Some(&mut self.stream),
+            "move" => Some(&mut self.move_channel),
+            _ => None
+        }
+    }
+
+    pub fn default_template(event: &str) -> Option<&'static str> {
+        match event {
+            "join" => Some(Self::DEFAULT_JOIN),
+            "leave" => Some(Self::DEFAULT_LEAVE),
+            "stream" => Some(Self::DEFAULT_STREAM),
+            "move" => Some(Self::DEFAULT_MOVE),
+            _ => None
+        }
+    }
+}
+
 impl GuildConfig {
+    /// メッセージの本文に辞書を適用して改行を読点にし、`prefix`(添付ファイルなど)を付けて長文を省略する
+    ///
+    /// botの読み上げと`say`サブコマンドで共通の処理。
+    pub fn reading_text(&self, prefix: &str, content: &str) -> String {
+        let content = self.dictionary.apply(content).unwrap_or(content.to_string()).replace('\n', "、");
+        self.length.apply(&format!("{prefix}{content}"))
+    }
+
+    /// 古いバージョンの設定を現在の形式に移行してから読み込む
+    pub fn from_value(mut config: serde_json::Value) -> Result<Self> {
+        if !config.is_object() {
+            anyhow::bail!("config must be an object");
+        }
+        let version = config.get("version").and_then(|version| version.as_u64()).unwrap_or(0) as usize;
+        if version > CONFIG_VERSION as usize {
+            anyhow::bail!("unsupported config version: {version}");
+        }
+        for migrate in &MIGRATIONS[version..] {
+            migrate(&mut config);
+        }
+        config["version"] = CONFIG_VERSION.into();
+        Ok(serde_json::from_value(config)?)
+    }
+
     pub fn load(guild_id: GuildId) -> Result<Self> {
-        let dir = Path::new(CONFIG_DIR).join(guild_id.0.to_string());
-        let config_path = dir.join(CONFIG_FILE);
-        let dict_path = dir.join(DICT_FILE);
-        if !config_path.exists() {
-            std::fs::create_dir_all(&dir)?;
-            let mut file = std::fs::File::create(&config_path)?;
-            let config = Self::default();
-            writeln!(file, "{}", serde_json::to_string_pretty(&config)?)?;
-        }
-        if !dict_path.exists() {
-            std::fs::create_dir_all(&dir)?;
-            let mut file = std::fs::File::create(&dict_path)?;
-            let dict = Dictionary::new();
-            writeln!(file, "{}", serde_json::to_string_pretty(&dict)?)?;
-        }
-        let config = std::fs::read_to_string(&config_path)?;
-        let dict = std::fs::read_to_string(&dict_path)?;
-        Ok(Self {
-            dictionary: serde_json::from_str(&dict)?,
-            ..serde_json::from_str(&config)?
-        })
+        storage::get().load_guild(guild_id)
     }
 
     pub fn save(&self, guild_id: GuildId) -> Result<()> {
-        let dir = Path::new(CONFIG_DIR).join(guild_id.0.to_string());
-        let mut file = std::fs::File::create(dir.join(CONFIG_FILE))?;
-        writeln!(file, "{}", serde_json::to_string_pretty(&self)?)?;
-        let mut file = std::fs::File::create(dir.join(DICT_FILE))?;
-        writeln!(file, "{}", serde_json::to_string_pretty(&self.dictionary)?)?;
-        Ok(())
+        storage::get().save_guild(guild_id, self)
     }
 }
 
 impl Config {
+    /// 保存されているすべてのギルドの設定を読み込む
     pub fn load() -> Result<Self> {
-        let mut config = Self::default();
-        for dir in std::fs::read_dir(CONFIG_DIR)? {
-            let guild_id = GuildId(dir?.file_name().into_string().unwrap().parse()?);
-            let guild_config = GuildConfig::load(guild_id)?;
-            config.0.insert(guild_id, guild_config);
-        }
-        Ok(config)
+        let guilds = load_guilds()?.into_iter()
+            .map(|(guild_id, guild_config)| (guild_id, Arc::new(RwLock::new(guild_config))))
+            .collect();
+        Ok(Self { guilds: std::sync::RwLock::new(guilds), saving: Mutex::new(()) })
     }
 
-    pub fn reload(&mut self) -> Result<()> {
-        *self = Self::load()?;
-        Ok(())
+    /// 手動で編集された設定と辞書を検証して読み込み直す
+    ///
+    /// 読み込みはロックの外で行い、読み込めたギルドの設定のみを置き換える。
+    /// 壊れているギルドはメモリ上の設定をそのまま使い続ける。
+    pub async fn reload(&self) -> Result<ReloadReport> {
+        let loaded = tokio::task::spawn_blocking(reload_guilds).await??;
+        let mut report = ReloadReport::default();
+        for (guild_id, guild_config) in loaded {
+            let guild_config = match guild_config {
+                Ok(guild_config) => guild_config,
+                Err(why) => {
+                    report.errors.push((guild_id, why));
+                    continue;
+                }
+            };
+            let current = self.guilds.read().unwrap().get(&guild_id).cloned();
+            match current {
+                Some(current) => *current.write().await = guild_config,
+                None => {
+                    self.guilds.write().unwrap().insert(guild_id, Arc::new(RwLock::new(guild_config)));
+                }
+            }
+            report.reloaded.push(guild_id);
+        }
+        Ok(report)
     }
 
-    pub fn save(&self) -> Result<()> {
-        for (&guild_id, config) in &self.0 {
-            config.save(guild_id)?;
+    /// ギルドの設定を返す。まだ読み込まれていない場合は読み込む
+    pub async fn guild(&self, guild_id: GuildId) -> Result<Arc<RwLock<GuildConfig>>> {
+        if let Some(guild_config) = self.guilds.read().unwrap().get(&guild_id) {
+            return Ok(guild_config.clone());
         }
-        Ok(())
+        let guild_config = tokio::task::spawn_blocking(move || GuildConfig::load(guild_id)).await?
+            .inspect_err(|why| error!(guild_id = %guild_id, "Failed to load config: {why}"))?;
+        // 読み込んでいる間に他のタスクが読み込んでいた場合はそちらを使う
+        let mut guilds = self.guilds.write().unwrap();
+        Ok(guilds.entry(guild_id).or_insert_with(|| Arc::new(RwLock::new(guild_config))).clone())
     }
 
-    pub fn guild_config(&mut self, guild_id: GuildId) -> &GuildConfig {
-        self.0.entry(guild_id).or_insert_with(|| {
-            GuildConfig::load(guild_id).unwrap()
-        });
-        self.0.get(&guild_id).unwrap()
+    fn loaded(&self) -> Vec<(GuildId, Arc<RwLock<GuildConfig>>)> {
+        self.guilds.read().unwrap().iter().map(|(&guild_id, config)| (guild_id, config.clone())).collect()
     }
 
-    pub fn guild_config_mut(&mut self, guild_id: GuildId) -> &mut GuildConfig {
-        self.0.entry(guild_id).or_insert_with(|| {
-            GuildConfig::load(guild_id).unwrap()
-        });
-        self.0.get_mut(&guild_id).unwrap()
+    /// ギルドの設定を保存する。ロックを保持したまま書き込まないように複製してから保存する
+    pub async fn save_guild(&self, guild_id: GuildId) -> Result<()> {
+        let Some(guild_config) = self.guilds.read().unwrap().get(&guild_id).cloned() else {
+            return Ok(());
+        };
+        // 古い内容で上書きしないように、複製する前に書き込みの順番を確保する
+        let _saving = self.saving.lock().await;
+        let guild_config = guild_config.read().await.clone();
+        tokio::task::spawn_blocking(move || guild_config.save(guild_id)).await?
+    }
+}
+
+/// 一部のギルドの設定が壊れていても他のギルドは読み込む
+fn load_guilds() -> Result<HashMap<GuildId, GuildConfig>> {
+    let mut guilds = HashMap::new();
+    for guild_id in storage::get().guild_ids()? {
+        match GuildConfig::load(guild_id) {
+            Ok(guild_config) => {
+                guilds.insert(guild_id, guild_config);
+            },
+            Err(why) => error!(guild_id = %guild_id, "Failed to load config: {why}")
+        }
     }
+    Ok(guilds)
 }
 
+/// バックアップを使わずに読み込み、辞書の正規表現も検証する
+fn reload_guilds() -> Result<Vec<(GuildId, Result<GuildConfig>)>> {
+    let guilds = storage::get().guild_ids()?.into_iter().map(|guild_id| {
+        let guild_config = storage::get().reload_guild(guild_id).and_then(|guild_config| {
+            guild_config.dictionary.validate()?;
+            Ok(guild_config)
+        });
+        (guild_id, guild_config)
+    }).collect();
+    Ok(guilds)
+}
 
 impl Drop for Config {
     fn drop(&mut self) {
-        let _ = self.save();
+        for (guild_id, guild_config) in self.loaded() {
+            let Ok(guild_config) = guild_config.try_read() else { continue; };
+            if let Err(why) = guild_config.save(guild_id) {
+                error!(guild_id = %guild_id, "Failed to save config: {why}");
+            }
+        }
     }
 }
+
+/// ギルドの設定を返す。まだ読み込まれていない場合は読み込む
+pub async fn guild_config(ctx: &Context, guild_id: GuildId) -> Result<Arc<RwLock<GuildConfig>>> {
+    let config = ctx.data.read().await.get::<ConfigData>().cloned().context("Expected ConfigData in TypeMap.")?;
+    config.guild(guild_id).await
+}
+
+/// ギルドの設定を保存する
+pub async fn save_guild(ctx: &Context, guild_id: GuildId) -> Result<()> {
+    let config = ctx.data.read().await.get::<ConfigData>().cloned().context("Expected ConfigData in TypeMap.")?;
+    config.save_guild(guild_id).await
+}
+
+#[test]
+fn test_length_config() {
+    let config = |policy, max_len| LengthConfig { max_len, policy, sentences: 2, max_queue_secs: 0 };
+    let text = "今日は晴れ。明日は雨。明後日は雪。";
+    assert_eq!(config(TruncatePolicy::Truncate, 100).apply(text), text);
+    assert_eq!(config(TruncatePolicy::Truncate, 8).apply(text), "今日は晴れ。明日 以下省略");
+    assert_eq!(config(TruncatePolicy::Sentence, 8).apply(text), "今日は晴れ。 以下省略");
+    assert_eq!(config(TruncatePolicy::Sentence, 4).apply("hello world"), "hell 以下省略");
+    assert_eq!(config(TruncatePolicy::Sentence, 8).apply("hello world"), "hello 以下省略");
+    assert_eq!(config(TruncatePolicy::Summarize, 8).apply(text), "長文のため省略");
+    assert_eq!(config(TruncatePolicy::FirstSentences, 100).apply(text), text);
+    assert_eq!(config(TruncatePolicy::FirstSentences, 14).apply("今日は、晴れ。明日は雨。明後日は雪。"), "今日は、晴れ。明日は雨。 以下省略");
+    assert_eq!(config(TruncatePolicy::FirstSentences, 8).apply(text), "今日は晴れ。 以下省略");
+}
+
+#[test]
+fn test_reading_text() {
+    let mut config = GuildConfig::default();
+    config.dictionary.insert(dictionary::DictItem { key: "zunda".into(), value: "ずんだ".into(), is_regex: false });
+    config.length = LengthConfig { max_len: 8, policy: TruncatePolicy::Truncate, ..LengthConfig::default() };
+    assert_eq!(config.reading_text("編集 ", "zunda\nおいしい"), "編集 ずんだ、お 以下省略");
+}
+
+#[test]
+fn test_permission_config() {
+    let config = PermissionConfig::default();
+    let moderator = RoleId(1);
+    assert!(!config.is_allowed("dictionary reset", &[], Permissions::empty(), Permissions::empty()));
+    assert!(config.is_allowed("dictionary reset", &[], Permissions::MANAGE_GUILD, Permissions::empty()));
+    assert!(config.is_allowed("dictionary reset", &[], Permissions::ADMINISTRATOR, Permissions::empty()));
+    assert!(config.is_allowed("dictionary add", &[], Permissions::empty(), Permissions::empty()));
+    assert!(!config.is_allowed("speaker", &[moderator], Permissions::empty(), Permissions::empty()));
+
+    let mut config = config;
+    config.rules.insert("dictionary".into(), PermissionRule { roles: [moderator].into(), permissions: Permissions::empty() });
+    config.rules.insert("speaker".into(), PermissionRule::default());
+    assert!(!config.is_allowed("dictionary add", &[], Permissions::empty(), Permissions::empty()));
+    assert!(config.is_allowed("dictionary add", &[moderator], Permissions::empty(), Permissions::empty()));
+    assert!(!config.is_allowed("dictionary reset", &[moderator], Permissions::empty(), Permissions::empty()));
+    assert!(config.is_allowed("speaker", &[], Permissions::empty(), Permissions::empty()));
+    assert!(!config.is_allowed("ignore", &[], Permissions::empty(), Permissions::MANAGE_GUILD));
+    assert!(config.is_allowed("ignore", &[], Permissions::MANAGE_GUILD, Permissions::MANAGE_GUILD));
+}
+
+#[test]
+fn test_config_migration() {
+    let fixtures = [
+        include_str!("../fixtures/config_v0.json"),
+        include_str!("../fixtures/config_v1.json")
+    ];
+    assert_eq!(fixtures.len(), CONFIG_VERSION as usize + 1);
+    for fixture in fixtures {
+        let config = GuildConfig::from_value(serde_json::from_str(fixture).unwrap()).unwrap();
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert!(config.time_signal);
+        assert_eq!(config.voice.speaker_id, 1);
+    }
+
+    let config = GuildConfig::from_value(serde_json::json!({ "time_signal": false })).unwrap();
+    assert_eq!(config.voice.speaker_id, default_speaker());
+    assert!(GuildConfig::from_value(serde_json::json!({ "version": CONFIG_VERSION + 1, "time_signal": false })).is_err());
+}
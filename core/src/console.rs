@@ -0,0 +1,242 @@
+use crate::log;
+use crate::session;
+use crate::config::{Config, GlobalConfig};
+use crate::event_handler;
+use crate::type_map::TrackInfo;
+use once_cell::sync::OnceCell;
+use serenity::prelude::*;
+use serenity::model::id::{GuildId, UserId};
+
+static CONTEXT: OnceCell<Context> = OnceCell::new();
+
+/// `help`で表示するコマンドの使い方と説明
+///
+/// 使い方の最初の単語がコマンド名になる。
+const USAGE: &[(&str, &str)] = &[
+    ("help", "Show this help"),
+    ("stop", "Leave all voice channels and shut down"),
+    ("guilds", "List guilds and their voice sessions"),
+    ("sessions", "List voice sessions"),
+    ("leave <guild_id>", "Force leave the voice channel of a guild"),
+    ("broadcast <message>", "Read out an announcement in all voice sessions"),
+    ("reload", "Reload config and dictionaries edited on disk"),
+    ("admin list", "List admin users"),
+    ("admin add <user_id>", "Add an admin user"),
+    ("admin remove <user_id>", "Remove an admin user"),
+    ("queue", "Show queued tracks of each voice session"),
+    ("log-level <filter>", "Change the log filter (same syntax as RUST_LOG)")
+];
+
+/// コンソールから実行できるコマンド
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Help,
+    Stop,
+    Guilds,
+    Sessions,
+    Leave(GuildId),
+    Broadcast(String),
+    Reload,
+    AdminList,
+    AdminAdd(UserId),
+    AdminRemove(UserId),
+    Queue,
+    LogLevel(String)
+}
+
+/// コマンドを実行するためのコンテキストを設定する。`cache_ready`で呼ぶ
+pub fn set_context(ctx: &Context) {
+    let _ = CONTEXT.set(ctx.clone());
+}
+
+/// 1行のコマンドを解釈する。空行の場合は`None`を返す
+///
+/// 引数は空白で区切る。`broadcast`のみ残りの文字列をそのまま使う。
+pub fn parse(line: &str) -> Result<Option<Command>, String> {
+    let line = line.trim();
+    let (name, rest) = line.split_once(char::is_whitespace)
+        .map_or((line, ""), |(name, rest)| (name, rest.trim()));
+    let args = rest.split_whitespace().collect::<Vec<_>>();
+    let parse_id = |id: &str| id.parse::<u64>().map_err(|_| format!("Invalid ID: {id}"));
+
+    let command = match (name, args.as_slice()) {
+        ("", _) => return Ok(None),
+        ("help", _) => Command::Help,
+        ("stop", []) => Command::Stop,
+        ("guilds", []) => Command::Guilds,
+        ("sessions", []) => Command::Sessions,
+        ("leave", [guild_id]) => Command::Leave(GuildId(parse_id(guild_id)?)),
+        ("broadcast", [_, ..]) => Command::Broadcast(rest.to_string()),
+        ("reload", []) => Command::Reload,
+        ("admin", ["list"]) => Command::AdminList,
+        ("admin", ["add", user_id]) => Command::AdminAdd(UserId(parse_id(user_id)?)),
+        ("admin", ["remove", user_id]) => Command::AdminRemove(UserId(parse_id(user_id)?)),
+        ("queue", []) => Command::Queue,
+        ("log-level", [filter]) => Command::LogLevel(filter.to_string()),
+        _ => return Err(usage_error(name))
+    };
+    Ok(Some(command))
+}
+
+/// 引数が正しくない場合は使い方を、コマンド名が正しくない場合は候補を返す
+fn usage_error(name: &str) -> String {
+    let command_name = |usage: &str| usage.split(' ').next().unwrap_or_default().to_string();
+    let usages = USAGE.iter()
+        .filter(|(usage, _)| command_name(usage) == name)
+        .map(|(usage, _)| *usage)
+        .collect::<Vec<_>>();
+    if !usages.is_empty() {
+        return format!("Usage: {}", usages.join(" | "));
+    }
+
+    let mut candidates = USAGE.iter()
+        .map(|(usage, _)| command_name(usage))
+        .filter(|command| command.starts_with(name))
+        .collect::<Vec<_>>();
+    candidates.dedup();
+    if candidates.is_empty() {
+        format!("Unknown command: {name} (type `help` to list commands)")
+    } else {
+        format!("Unknown command: {name} (did you mean {}?)", candidates.join(", "))
+    }
+}
+
+/// `stop`以外のコマンドを実行して結果を標準出力に表示する
+pub async fn execute(command: Command, config: &Config) {
+    let ctx = CONTEXT.get();
+    match (command, ctx) {
+        (Command::Help, _) => {
+            for (usage, description) in USAGE {
+                println!("  {usage:<24}{description}");
+            }
+        },
+        (Command::Stop, _) => {},
+        (Command::Reload, _) => reload(config).await,
+        (Command::AdminList, _) => match GlobalConfig::load() {
+            Ok(global_config) => {
+                for user_id in &global_config.admin_user {
+                    println!("{user_id}");
+                }
+            },
+            Err(why) => println!("Failed to load global config: {why:#}")
+        },
+        (Command::AdminAdd(user_id), _) => update_admin(|config| config.add_admin(user_id)),
+        (Command::AdminRemove(user_id), _) => update_admin(|config| config.remove_admin(user_id)),
+        (Command::LogLevel(filter), _) => match log::set_filter(&filter) {
+            Ok(()) => println!("Log filter changed to {filter}"),
+            Err(why) => println!("Failed to change log filter: {why:#}")
+        },
+        (_, None) => println!("Not ready yet"),
+        (Command::Guilds, Some(ctx)) => guilds(ctx).await,
+        (Command::Sessions, Some(ctx)) => sessions(ctx).await,
+        (Command::Leave(guild_id), Some(ctx)) => match session::disconnect(ctx, guild_id).await {
+            Ok(()) => println!("Left the voice channel of guild {guild_id}"),
+            Err(why) => println!("Failed to leave guild {guild_id}: {why:#}")
+        },
+        (Command::Broadcast(message), Some(ctx)) => broadcast(ctx, &message).await,
+        (Command::Queue, Some(ctx)) => queue(ctx).await
+    }
+}
+
+/// 手動で編集された設定と辞書を読み込み直して、読み込めなかったファイルを表示する
+async fn reload(config: &Config) {
+    if let Err(why) = GlobalConfig::reload() {
+        println!("Failed to reload global config: {why:#}");
+    }
+    match config.reload().await {
+        Ok(report) => {
+            for (guild_id, why) in &report.errors {
+                println!("Failed to reload config of guild {guild_id} (keeping current config): {why:#}");
+            }
+            println!("Reloaded {} guilds ({} failed)", report.reloaded.len(), report.errors.len());
+        },
+        Err(why) => println!("Failed to reload config: {why:#}")
+    }
+}
+
+fn update_admin(f: impl FnOnce(&mut GlobalConfig) -> bool) {
+    let result = GlobalConfig::load().and_then(|mut global_config| {
+        let is_changed = f(&mut global_config);
+        global_config.save()?;
+        Ok(is_changed)
+    });
+    match result {
+        Ok(true) => println!("Admin users updated"),
+        Ok(false) => println!("Nothing to change"),
+        Err(why) => println!("Failed to update global config: {why:#}")
+    }
+}
+
+async fn guilds(ctx: &Context) {
+    for guild_id in ctx.cache.guilds() {
+        let name = guild_id.name(&ctx.cache).unwrap_or_default();
+        let members = guild_id.to_guild_cached(&ctx.cache).map_or(0, |guild| guild.member_count);
+        let session = match session::get(ctx, guild_id).await {
+            Some(session) => format!(" [voice {} / text {}]", session.voice_channel, session.text_channel),
+            None => String::new()
+        };
+        println!("{guild_id} {name} ({members} members){session}");
+    }
+}
+
+async fn sessions(ctx: &Context) {
+    let sessions = session::all(ctx).await;
+    for (guild_id, session) in &sessions {
+        println!(
+            "{guild_id}: voice {} / text {} (idle {}s)",
+            session.voice_channel,
+            session.text_channel,
+            session.last_activity.elapsed().as_secs()
+        );
+    }
+    println!("{} voice sessions", sessions.len());
+}
+
+async fn broadcast(ctx: &Context, message: &str) {
+    let sessions = session::all(ctx).await;
+    let mut count = 0;
+    for (guild_id, _) in &sessions {
+        match event_handler::speak(ctx, *guild_id, message, TrackInfo::announcement()).await {
+            Ok(_) => count += 1,
+            Err(why) => println!("Failed to broadcast to guild {guild_id}: {why:#}")
+        }
+    }
+    println!("Broadcast to {count}/{} voice sessions", sessions.len());
+}
+
+async fn queue(ctx: &Context) {
+    let Some(manager) = songbird::get(ctx).await else {
+        println!("Failed to retrieve Songbird voice client");
+        return;
+    };
+    for (guild_id, _) in session::all(ctx).await {
+        let Some(handle) = manager.get(guild_id) else { continue; };
+        let tracks = handle.lock().await.queue().current_queue();
+        let queued = tracks.iter()
+            .filter_map(|track| track.metadata().duration)
+            .sum::<std::time::Duration>();
+        let current = match tracks.first() {
+            Some(track) => track.typemap().read().await.get::<TrackInfo>().map(|info| info.text.clone()),
+            None => None
+        };
+        println!(
+            "{guild_id}: {} tracks ({}s){}",
+            tracks.len(),
+            queued.as_secs(),
+            current.map(|text| format!(", playing \"{text}\"")).unwrap_or_default()
+        );
+    }
+}
+
+#[test]
+fn test_parse_console_command() {
+    assert_eq!(parse("  "), Ok(None));
+    assert_eq!(parse("stop"), Ok(Some(Command::Stop)));
+    assert_eq!(parse("leave 123"), Ok(Some(Command::Leave(GuildId(123)))));
+    assert_eq!(parse("broadcast  まもなく 再起動するのだ "), Ok(Some(Command::Broadcast("まもなく 再起動するのだ".into()))));
+    assert_eq!(parse("admin add 1"), Ok(Some(Command::AdminAdd(UserId(1)))));
+    assert_eq!(parse("admin add x"), Err("Invalid ID: x".into()));
+    assert_eq!(parse("leave"), Err("Usage: leave <guild_id>".into()));
+    assert_eq!(parse("admin"), Err("Usage: admin list | admin add <user_id> | admin remove <user_id>".into()));
+    assert_eq!(parse("s"), Err("Unknown command: s (did you mean stop, sessions?)".into()));
+}
@@ -0,0 +1,98 @@
+use crate::session;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tracing::{error, info, warn};
+use songbird::{Event, EventContext, EventHandler as VoiceEventHandler};
+use serenity::async_trait;
+use serenity::prelude::*;
+use serenity::model::id::GuildId;
+
+/// 再接続を試みる回数
+const MAX_RETRIES: u32 = 5;
+/// 最初の再接続までの待ち時間。失敗するたびに2倍にする
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// ボイスサーバーからの切断を検知して再接続する
+///
+/// `Call`ごとに1つ作り、クローンしたものを切断と再接続の両方のイベントに登録する。
+#[derive(Clone)]
+pub struct DriverEvents(Arc<DriverState>);
+
+struct DriverState {
+    ctx: Context,
+    guild_id: GuildId,
+    is_reconnecting: AtomicBool
+}
+
+impl DriverEvents {
+    pub fn new(ctx: &Context, guild_id: GuildId) -> Self {
+        Self(Arc::new(DriverState { ctx: ctx.clone(), guild_id, is_reconnecting: AtomicBool::new(false) }))
+    }
+}
+
+#[async_trait]
+impl VoiceEventHandler for DriverEvents {
+    async fn act(&self, event: &EventContext<'_>) -> Option<Event> {
+        match event {
+            EventContext::DriverReconnect(data) => {
+                info!(guild_id = %self.0.guild_id, channel_id = ?data.channel_id, "driver reconnected");
+            },
+            EventContext::DriverDisconnect(data) => {
+                // 理由がない場合は切断や移動など自身が要求したものなので何もしない
+                let reason = data.reason?;
+                warn!(guild_id = %self.0.guild_id, kind = ?data.kind, reason = ?reason, "driver disconnected");
+
+                // 再接続の失敗でも呼ばれるので、再接続中は何もしない
+                if self.0.is_reconnecting.swap(true, Ordering::AcqRel) {
+                    return None;
+                }
+                let state = Arc::clone(&self.0);
+                tokio::spawn(async move {
+                    reconnect(&state.ctx, state.guild_id).await;
+                    state.is_reconnecting.store(false, Ordering::Release);
+                });
+            },
+            _ => {}
+        }
+        None
+    }
+}
+
+/// 間隔を空けながら再接続を試み、最終的に失敗した場合はテキストチャンネルに通知して切断する
+async fn reconnect(ctx: &Context, guild_id: GuildId) {
+    let Some(manager) = songbird::get(ctx).await else { return; };
+
+    for attempt in 1.. {
+        let Some(delay) = backoff(attempt) else { break; };
+        tokio::time::sleep(delay).await;
+
+        // 待っている間に切断された
+        let Some(session) = session::get(ctx, guild_id).await else { return; };
+
+        info!(guild_id = %guild_id, attempt = attempt, "reconnect");
+        let (_handle, result) = manager.join(guild_id, session.voice_channel).await;
+        match result {
+            Ok(()) => return,
+            Err(why) => warn!(guild_id = %guild_id, attempt = attempt, "Failed to reconnect: {why}")
+        }
+    }
+
+    let Some(session) = session::get(ctx, guild_id).await else { return; };
+    error!(guild_id = %guild_id, "give up reconnecting");
+    let _ = session.text_channel.say(&ctx.http, "ボイスチャンネルに再接続できなかったので、読み上げを終了するのだ。").await;
+    let _ = session::disconnect(ctx, guild_id).await;
+}
+
+/// `attempt`回目の再接続までの待ち時間を返す。再接続を諦める場合は`None`を返す
+fn backoff(attempt: u32) -> Option<Duration> {
+    (1..=MAX_RETRIES).contains(&attempt).then(|| INITIAL_BACKOFF * 2u32.pow(attempt - 1))
+}
+
+#[test]
+fn test_backoff() {
+    let delays = (1..).map_while(backoff).collect::<Vec<_>>();
+    assert_eq!(delays, [1, 2, 4, 8, 16].map(Duration::from_secs));
+    assert_eq!(backoff(0), None);
+    assert_eq!(backoff(MAX_RETRIES + 1), None);
+}
@@ -1,13 +1,19 @@
 use crate::commands;
 use crate::synthesis;
-use crate::type_map::{TextChannelId, ConfigData, ConnectedChannel};
+use crate::session;
+use crate::console;
+use crate::config::{self, GlobalConfig};
+use crate::type_map::{ConfigData, TrackInfo, RateLimit};
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use chrono::{Timelike, Datelike};
 use anyhow::Result;
-use tracing::{error, info};
+use tracing::{error, info, info_span, Instrument};
+use songbird::tracks::{TrackHandle, TrackQueue};
+use songbird::input::Input;
 use serenity::{
     async_trait,
+    http::{request::RequestBuilder, routing::RouteInfo},
     prelude::*,
     model::{
         prelude::*,
@@ -17,8 +23,6 @@ use serenity::{
     }
 };
 
-const MAX_TEXT_LEN: usize = 255;
-
 #[derive(Debug, Default)]
 pub struct Handler {
     is_loop_running: AtomicBool
@@ -30,59 +34,62 @@ impl EventHandler for Handler {
     async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
         match interaction {
             Interaction::ApplicationCommand(command) => {
-                if let Err(why) = match command.data.name.as_str() {
-                    "join" => commands::join::run(&ctx, &command).await,
-                    "leave" => commands::leave::run(&ctx, &command).await,
-                    "version" => commands::version::run(&ctx, &command).await,
-                    "skip" => commands::skip::run(&ctx, &command).await,
-                    "dictionary" => commands::dictionary::run(&ctx, &command).await,
-                    "time-signal" => commands::time_signal::run(&ctx, &command).await,
-                    "status" => commands::status::run(&ctx, &command).await,
-                    "speaker" => commands::speaker::run(&ctx, &command).await,
-                    "log" => commands::log::run(&ctx, &command).await,
-                    _ => unimplemented!()
-                } {
-                    error!("Cannot respond to slash command: {why}");
-                }
+                // `/log`でコマンドとギルドごとに絞り込めるように、実行中のログに付ける
+                let span = info_span!(
+                    "command",
+                    command = %command.data.name,
+                    guild_id = command.guild_id.map(|guild_id| guild_id.0)
+                );
+                async {
+                    if let Err(why) = commands::dispatch(&ctx, &command).await {
+                        error!("Cannot respond to slash command: {why}");
+                    }
+                    if let Some(guild_id) = command.guild_id {
+                        if let Err(why) = config::save_guild(&ctx, guild_id).await {
+                            error!(guild_id = %guild_id, "Failed to save config: {why}");
+                        }
+                    }
+                }.instrument(span).await
             },
             _ => {}
         }
-
-        {
-            let data_read = ctx.data.read().await;
-            let config = data_read.get::<ConfigData>().unwrap();
-            let _ = config.lock().unwrap().save();
-        }
     }
 
-    async fn ready(&self, ctx: Context, ready: Ready) {
+    async fn ready(&self, ctx: Context, _ready: Ready) {
         synthesis::initialize();
-        for guild in &ready.guilds {
-            let guild_id = guild.id;
-            let _commands = GuildId::set_application_commands(&guild_id, &ctx.http, |commands| {
-                commands
-                    .create_application_command(|cmd| commands::join::register(cmd))
-                    .create_application_command(|cmd| commands::leave::register(cmd))
-                    .create_application_command(|cmd| commands::version::register(cmd))
-                    .create_application_command(|cmd| commands::skip::register(cmd))
-                    .create_application_command(|cmd| commands::dictionary::register(cmd))
-                    .create_application_command(|cmd| commands::time_signal::register(cmd))
-                    .create_application_command(|cmd| commands::status::register(cmd))
-                    .create_application_command(|cmd| commands::speaker::register(cmd))
-                    .create_application_command(|cmd| commands::log::register(cmd))
-            }).await.unwrap();
-
-            {
-                let data_read = ctx.data.read().await;
-                let config = data_read.get::<ConfigData>().unwrap();
-                let _ = config.lock().unwrap().reload();
+
+        let global = GlobalConfig::load().map(|config| config.global_commands).unwrap_or_default();
+        if let Err(why) = commands::sync_global(&ctx.http, global).await {
+            error!("Failed to register global commands: {why}");
+        }
+
+        let config = ctx.data.read().await.get::<ConfigData>().cloned();
+        if let Some(config) = config {
+            match config.reload().await {
+                Ok(report) => for (guild_id, why) in report.errors {
+                    error!(guild_id = %guild_id, "Failed to reload config: {why:#}");
+                },
+                Err(why) => error!("Failed to reload config: {why}")
             }
         }
         ctx.set_activity(Activity::playing(format!("v{}", env!("CARGO_PKG_VERSION")))).await;
     }
 
+    /// 起動時と新しくギルドに参加したときにスラッシュコマンドを登録する
+    async fn guild_create(&self, ctx: Context, guild: Guild, _is_new: bool) {
+        let global = GlobalConfig::load().map(|config| config.global_commands).unwrap_or_default();
+        if let Err(why) = commands::sync_guild(&ctx.http, guild.id, global).await {
+            error!(guild_id = %guild.id, "Failed to register commands: {why}");
+        }
+    }
+
     async fn cache_ready(&self, ctx: Context, guilds: Vec<GuildId>) {
         if !self.is_loop_running.load(Ordering::Relaxed) {
+            console::set_context(&ctx);
+
+            // 前回接続していたボイスチャンネルに再接続する
+            session::restore(&ctx).await;
+
             let ctx = Arc::new(ctx);
             tokio::spawn(async move {
                 loop {
@@ -91,6 +98,10 @@ impl EventHandler for Handler {
                         .duration_since(std::time::SystemTime::UNIX_EPOCH)
                         .unwrap()
                         .as_secs();
+                    #[allow(clippy::manual_is_multiple_of)]
+                    if time % 60 == 0 {
+                        disconnect_idle(&ctx).await;
+                    }
                     if ((time % 3600) / 60, time % 60) == (0, 0) {
                         for guild in guilds.clone() {
                             let is_in_vc = guild.to_guild_cached(&ctx.cache)
@@ -99,7 +110,7 @@ impl EventHandler for Handler {
                                 let ctx = Arc::clone(&ctx);
                                 tokio::spawn(async move {
                                     let text = time_message();
-                                    let _ = speak(&ctx, guild, &text).await;
+                                    let _ = speak(&ctx, guild, &text, TrackInfo::announcement()).await;
                                 });
                             }
                         }
@@ -113,149 +124,755 @@ impl EventHandler for Handler {
     }
 
     async fn message(&self, ctx: Context, msg: Message) {
-        let self_id = ctx.cache.current_user_id();
+        let Some(guild_id) = reading_guild(&ctx, &msg).await else { return; };
 
-        // 自身のメッセージは無視
-        if msg.author.id == self_id {
-            return;
+        let mut text = String::new();
+
+        match msg.kind {
+            MessageType::ThreadCreated => text.push_str("新規スレッド "),
+            MessageType::InlineReply => text.push_str("リプライ "),
+            _ => {}
         }
+        text.push_str(&message_extras(&ctx, guild_id, &msg).await);
+        let content = message_content(&ctx, guild_id, &msg).await;
 
-        let guild = msg.guild(&ctx.cache).unwrap();
+        // 長文は省略
+        let text = reading_text(&ctx, guild_id, &text, &content).await;
 
-        // 読み上げるテキストチャンネルを取得
-        let Some(text_channel) = ({
-            let data_read = ctx.data.read().await;
-            let channel_id = data_read.get::<TextChannelId>().unwrap();
-            let text_channel = channel_id.lock().unwrap().get(&guild.id).cloned();
-            text_channel
-        }) else { return; };
-
-        // 自身がVCにいるときのみ読み上げる
-        if msg.channel_id == text_channel && guild.voice_states.contains_key(&self_id) {
-            let mut text = String::new();
-
-            match msg.kind {
-                MessageType::ThreadCreated => text.push_str("新規スレッド "),
-                MessageType::InlineReply => text.push_str("リプライ "),
-                _ => {}
-            }
-            if !msg.attachments.is_empty() {
-                text.push_str("添付ファイル ");
-            }
+        let info = TrackInfo { message_ids: vec![msg.id], author: Some(msg.author.id), ..Default::default() };
+        let _ = speak(&ctx, guild_id, text.trim(), info).await;
+    }
 
-            let mut content = msg.content.clone();
-            for user in &msg.mentions {
-                let id = user.id;
-                let name = user.nick_in(&ctx.http, guild.id).await.unwrap_or(user.name.clone());
-                content = content.replace(&format!("<@{id}>"), &format!("@{name}"));
+    async fn message_update(&self, ctx: Context, old: Option<Message>, new: Option<Message>, event: MessageUpdateEvent) {
+        // 埋め込みの展開などでも呼ばれるので本文が変わった場合のみ読み上げる
+        if event.content.is_none() {
+            return;
+        }
+        let new = match new {
+            Some(new) => new,
+            // REST で取得したメッセージにはギルドIDもメンバー情報も含まれない
+            None => match event.channel_id.message(&ctx.http, event.id).await {
+                Ok(mut new) => {
+                    new.guild_id = event.guild_id;
+                    new
+                },
+                Err(_) => return
             }
+        };
+        let Some(guild_id) = reading_guild(&ctx, &new).await else { return; };
 
-            for role in &msg.mention_roles {
-                let id = role.0;
-                let name = role.to_role_cached(&ctx.cache).map_or(String::new(), |r| r.name.clone());
-                print!("content: {content:?} -> ");
-                content = content.replace(&format!("<@&{id}>"), &format!("@{name}"));
-                println!("{content:?}");
-            }
+        let Ok(guild_config) = config::guild_config(&ctx, guild_id).await else { return; };
+        let is_enabled = guild_config.read().await.reading.edit;
+        if !is_enabled {
+            return;
+        }
 
+        // 編集前のメッセージがキャッシュにあれば変更された部分のみ読み上げる
+        let mut edited = new.clone();
+        if let Some(old) = old {
+            edited.content = edited_part(&old.content, &new.content).to_string();
+        }
+        if edited.content.trim().is_empty() {
+            return;
+        }
 
-            content = {
-                let data_read = ctx.data.read().await;
-                let config = data_read.get::<ConfigData>().unwrap();
-                let mut config_lock = config.lock().unwrap();
-                let dict = &config_lock.guild_config(guild.id).dictionary;
-                dict.apply(&content)
-                    .unwrap_or(msg.content.clone())
-                    .replace("\n", "、")
-            };
+        let content = message_content(&ctx, guild_id, &edited).await;
+        let text = reading_text(&ctx, guild_id, "編集 ", &content).await;
 
-            text.push_str(&content);
+        let info = TrackInfo { message_ids: vec![new.id], author: Some(new.author.id), ..Default::default() };
+        let _ = speak(&ctx, guild_id, text.trim(), info).await;
+    }
 
-            // 長文は省略
-            if text.chars().count() > MAX_TEXT_LEN {
-                text = format!("{} 以下省略", text.chars().take(MAX_TEXT_LEN).collect::<String>());
-            }
+    async fn message_delete(&self, ctx: Context, _channel_id: ChannelId, message_id: MessageId, guild_id: Option<GuildId>) {
+        let Some(guild_id) = guild_id else { return; };
+        let _ = cancel_messages(&ctx, guild_id, &[message_id]).await;
+    }
+
+    async fn message_delete_bulk(&self, ctx: Context, _channel_id: ChannelId, message_ids: Vec<MessageId>, guild_id: Option<GuildId>) {
+        let Some(guild_id) = guild_id else { return; };
+        let _ = cancel_messages(&ctx, guild_id, &message_ids).await;
+    }
+
+    async fn reaction_add(&self, ctx: Context, reaction: Reaction) {
+        let (Some(guild_id), Some(user_id)) = (reaction.guild_id, reaction.user_id) else { return; };
+        if user_id == ctx.cache.current_user_id() {
+            return;
+        }
+        if !is_reading_channel(&ctx, guild_id, reaction.channel_id).await {
+            return;
+        }
 
-            let _ = speak(&ctx, guild.id, text.trim()).await;
+        let emoji = match &reaction.emoji {
+            ReactionType::Custom { name: Some(name), .. } => name.clone(),
+            ReactionType::Unicode(emoji) => emoji.clone(),
+            _ => return
+        };
+
+        let Ok(guild_config) = config::guild_config(&ctx, guild_id).await else { return; };
+        let is_target = {
+            let config = guild_config.read().await;
+            let roles = reaction.member.as_ref().map_or(&[][..], |member| &member.roles);
+            config.reading.reaction && !config.ignore.is_ignored(user_id, roles, "")
+        };
+        if !is_target {
+            return;
         }
+
+        let name = match reaction.member.as_ref().and_then(|member| member.nick.clone()) {
+            Some(nick) => nick,
+            None => match user_id.to_user(&ctx).await {
+                Ok(user) => user.nick_in(&ctx.http, guild_id).await.unwrap_or(user.name),
+                Err(_) => return
+            }
+        };
+
+        let text = apply_dictionary(&ctx, guild_id, &format!("{name}さんがリアクション {emoji}")).await;
+        let info = TrackInfo { message_ids: vec![reaction.message_id], author: Some(user_id), ..Default::default() };
+        let _ = speak(&ctx, guild_id, text.trim(), info).await;
     }
 
-    /// 非botのユーザーが全員VCを抜けたら自動的に切断する
+    /// 入退室などを読み上げ、非botのユーザーが全員VCを抜けたら自動的に切断する
+    /// 接続していない場合は自動接続のルールに従って接続する
     async fn voice_state_update(&self, ctx: Context, old: Option<VoiceState>, new: VoiceState) {
-        // 自動退室
-
         let Some(guild_id) = new.guild_id else { return; };
-        let Some(voice_channel) = ({
-            let data_read = ctx.data.read().await;
-            let connected = data_read.get::<ConnectedChannel>().unwrap();
-            let lock = connected.lock().unwrap();
-            lock.get(&guild_id).cloned()
-        }) else { return; };
+        let Some(voice_channel) = session::get(&ctx, guild_id).await.map(|session| session.voice_channel) else {
+            auto_join(&ctx, guild_id, old.as_ref(), &new).await;
+            return;
+        };
+
+        // 自身がモデレーターに移動または切断させられた場合
+        if new.user_id == ctx.cache.current_user_id() {
+            match new.channel_id {
+                Some(channel_id) if channel_id != voice_channel => {
+                    info!(guild_id = %guild_id, voice_channel = %channel_id, "moved");
+                    if let Err(why) = session::move_to(&ctx, guild_id, channel_id).await {
+                        error!("Failed to save sessions: {why}");
+                    }
+                },
+                Some(_) => {},
+                None => {
+                    info!(guild_id = %guild_id, "disconnected");
+                    let _ = session::disconnect(&ctx, guild_id).await;
+                }
+            }
+            return;
+        }
+
+        announce_voice_state(&ctx, guild_id, voice_channel, old.as_ref(), &new).await;
+
+        // 自動退室
 
         // VCから退出あるいは別のVCに移動
         if old.and_then(|state| state.channel_id) == Some(voice_channel) &&
-            !new.channel_id.map_or(false, |id| id == voice_channel)
+            new.channel_id != Some(voice_channel)
         {
-            let Ok(Channel::Guild(channel)) = voice_channel.to_channel(&ctx.http).await else { return; };
-            let Ok(members) = channel.members(&ctx.cache).await else { return; };
-
-            if members.is_empty() || members.iter().all(|member| member.user.bot) {
-                info!("auto disconnect");
-                let manager = songbird::get(&ctx).await.unwrap();
+            if !is_empty_channel(&ctx, voice_channel).await {
+                return;
+            }
+            let Ok(guild_config) = config::guild_config(&ctx, guild_id).await else { return; };
+            let (grace_secs, stay) = {
+                let config = &guild_config.read().await.auto_disconnect;
+                (config.grace_secs, config.stay)
+            };
+            if stay {
+                return;
+            }
 
+            // 猶予時間の間に誰かが戻ってきた場合は切断しない
+            tokio::spawn(async move {
+                tokio::time::sleep(std::time::Duration::from_secs(grace_secs)).await;
+                let session = session::get(&ctx, guild_id).await;
+                if session.is_some_and(|session| session.voice_channel == voice_channel)
+                    && is_empty_channel(&ctx, voice_channel).await
                 {
-                    let data_read = ctx.data.read().await;
-                    let connected = data_read.get::<ConnectedChannel>().unwrap();
-                    let mut lock = connected.lock().unwrap();
-                    lock.remove(&guild_id);
+                    info!(guild_id = %guild_id, "auto disconnect");
+                    auto_disconnect(&ctx, guild_id).await;
                 }
+            });
+        }
+    }
 
-                if let Some(handle) = manager.get(guild_id) {
-                    let handler = handle.lock().await;
-                    handler.queue().modify_queue(|q| q.clear());
-                }
-                let _ = manager.leave(guild_id).await;
-
-            }
+    /// 接続中のボイスチャンネルか読み上げるテキストチャンネルが削除された場合は切断する
+    async fn channel_delete(&self, ctx: Context, channel: &GuildChannel) {
+        let Some(session) = session::get(&ctx, channel.guild_id).await else { return; };
+        if channel.id == session.voice_channel || channel.id == session.text_channel {
+            info!(guild_id = %channel.guild_id, channel_id = %channel.id, "channel deleted");
+            let _ = session::disconnect(&ctx, channel.guild_id).await;
         }
     }
 
     async fn resume(&self, ctx: Context, resumed_event: ResumedEvent) {
         info!(resumed_event = ?resumed_event, "resume event");
-        let connected = {
-            let data_read = ctx.data.read().await;
-            let connected = data_read.get::<ConnectedChannel>().unwrap();
-            let lock = connected.lock().unwrap();
-            lock.iter().map(|(&g, &c)| (g, c)).collect::<Vec<_>>()
-        };
         let manager = songbird::get(&ctx).await.unwrap();
-        for (guild_id, channel_id) in connected {
-            let _ = manager.join(guild_id, channel_id).await;
+        for (guild_id, session) in session::all(&ctx).await {
+            // 切断中に削除されたチャンネルには再接続しない
+            if session.voice_channel.to_channel_cached(&ctx.cache).is_none() {
+                let _ = session::disconnect(&ctx, guild_id).await;
+                continue;
+            }
+            let _ = manager.join(guild_id, session.voice_channel).await;
+        }
+    }
+}
+
+/// メッセージが読み上げ対象であれば、読み上げるギルドのIDを返す
+async fn reading_guild(ctx: &Context, msg: &Message) -> Option<GuildId> {
+    // 自身のメッセージは無視
+    if msg.author.id == ctx.cache.current_user_id() {
+        return None;
+    }
+
+    let guild_id = msg.guild_id?;
+
+    // 自身がVCにいて、読み上げるテキストチャンネルに送られたメッセージのみ読み上げる
+    if !is_reading_channel(ctx, guild_id, msg.channel_id).await {
+        return None;
+    }
+
+    // 無視リストに該当するメッセージは読み上げない
+    let guild_config = config::guild_config(ctx, guild_id).await.ok()?;
+    // メンバー情報がないメッセージはキャッシュか API からロールを取得し、取得できなければ読み上げない
+    let roles = match &msg.member {
+        Some(member) => member.roles.clone(),
+        None if msg.webhook_id.is_some() => Vec::new(),
+        None => guild_id.member(ctx, msg.author.id).await.ok()?.roles
+    };
+    let is_ignored = guild_config.read().await.ignore.is_ignored(msg.author.id, &roles, &msg.content);
+
+    (!is_ignored).then_some(guild_id)
+}
+
+/// 指定したチャンネルが読み上げるテキストチャンネルであり、かつ自身がVCにいるか判定する
+async fn is_reading_channel(ctx: &Context, guild_id: GuildId, channel_id: ChannelId) -> bool {
+    let text_channel = session::get(ctx, guild_id).await.map(|session| session.text_channel);
+    let is_in_vc = guild_id.to_guild_cached(&ctx.cache)
+        .is_some_and(|guild| guild.voice_states.contains_key(&ctx.cache.current_user_id()));
+
+    text_channel == Some(channel_id) && is_in_vc
+}
+
+/// メッセージ本文のメンションを名前に置き換える
+async fn message_content(ctx: &Context, guild_id: GuildId, msg: &Message) -> String {
+    let mut content = msg.content.clone();
+    for user in &msg.mentions {
+        let id = user.id;
+        let name = user.nick_in(&ctx.http, guild_id).await.unwrap_or(user.name.clone());
+        content = content.replace(&format!("<@{id}>"), &format!("@{name}"));
+    }
+
+    for role in &msg.mention_roles {
+        let id = role.0;
+        let name = role.to_role_cached(&ctx.cache).map_or(String::new(), |r| r.name.clone());
+        content = content.replace(&format!("<@&{id}>"), &format!("@{name}"));
+    }
+
+    content
+}
+
+/// 添付ファイル・スタンプ・埋め込みを読み上げる文章にする
+async fn message_extras(ctx: &Context, guild_id: GuildId, msg: &Message) -> String {
+    let config = match config::guild_config(ctx, guild_id).await {
+        Ok(guild_config) => guild_config.read().await.reading.clone(),
+        Err(_) => return String::new()
+    };
+
+    let mut text = String::new();
+
+    if config.attachment && !msg.attachments.is_empty() {
+        let kinds = msg.attachments.iter()
+            .map(|attachment| AttachmentKind::new(attachment.content_type.as_deref(), &attachment.filename));
+        text.push_str(&describe_attachments(kinds));
+        text.push(' ');
+    }
+
+    let is_image = |attachment: &Attachment| {
+        AttachmentKind::new(attachment.content_type.as_deref(), &attachment.filename) == AttachmentKind::Image
+    };
+    if config.alt_text && msg.attachments.iter().any(is_image) {
+        for description in attachment_descriptions(ctx, msg).await {
+            text.push_str(&description);
+            text.push(' ');
+        }
+    }
+
+    if config.sticker {
+        for sticker in &msg.sticker_items {
+            text.push_str(&format!("スタンプ {} ", sticker.name));
+        }
+    }
+
+    if config.embed {
+        for title in msg.embeds.iter().filter_map(|embed| embed.title.as_ref()) {
+            text.push_str(&format!("{title} "));
+        }
+    }
+
+    if text.is_empty() {
+        text
+    } else {
+        apply_dictionary(ctx, guild_id, &text).await + " "
+    }
+}
+
+/// 添付ファイルの説明文(代替テキスト)を取得する
+///
+/// serenityの`Attachment`には`description`が含まれないので、メッセージをJSONのまま取得する。
+async fn attachment_descriptions(ctx: &Context, msg: &Message) -> Vec<String> {
+    let request = RequestBuilder::new(RouteInfo::GetMessage {
+        channel_id: msg.channel_id.0,
+        message_id: msg.id.0
+    }).build();
+    let Ok(value) = ctx.http.fire::<serde_json::Value>(request).await else {
+        return Vec::new();
+    };
+    value["attachments"].as_array().map_or(Vec::new(), |attachments| {
+        attachments.iter()
+            .filter_map(|attachment| attachment["description"].as_str())
+            .map(|description| description.to_string())
+            .collect()
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum AttachmentKind {
+    Image,
+    Video,
+    Audio,
+    Pdf,
+    Text,
+    Other
+}
+
+impl AttachmentKind {
+    fn new(content_type: Option<&str>, filename: &str) -> Self {
+        let extension = filename.rsplit_once('.').map(|(_, ext)| ext.to_ascii_lowercase()).unwrap_or_default();
+        match (content_type.unwrap_or_default(), extension.as_str()) {
+            (mime, _) if mime.starts_with("image/") => Self::Image,
+            (mime, _) if mime.starts_with("video/") => Self::Video,
+            (mime, _) if mime.starts_with("audio/") => Self::Audio,
+            ("application/pdf", _) => Self::Pdf,
+            (mime, _) if mime.starts_with("text/") => Self::Text,
+            (_, "png" | "jpg" | "jpeg" | "gif" | "webp") => Self::Image,
+            (_, "mp4" | "mov" | "webm") => Self::Video,
+            (_, "mp3" | "wav" | "ogg" | "m4a" | "flac") => Self::Audio,
+            (_, "pdf") => Self::Pdf,
+            (_, "txt") => Self::Text,
+            _ => Self::Other
+        }
+    }
+
+    /// 読み上げる名前と助数詞
+    fn name(self) -> (&'static str, &'static str) {
+        match self {
+            Self::Image => ("画像", "枚"),
+            Self::Video => ("動画", "本"),
+            Self::Audio => ("音声ファイル", "個"),
+            Self::Pdf => ("PDFファイル", "個"),
+            Self::Text => ("テキストファイル", "個"),
+            Self::Other => ("添付ファイル", "個")
+        }
+    }
+}
+
+/// 添付ファイルを種類ごとに数えて「画像2枚 PDFファイル」のような文章にする
+fn describe_attachments(kinds: impl Iterator<Item = AttachmentKind>) -> String {
+    let mut counts = std::collections::BTreeMap::new();
+    for kind in kinds {
+        *counts.entry(kind).or_insert(0) += 1;
+    }
+    counts.into_iter().map(|(kind, count)| {
+        let (name, counter) = kind.name();
+        if count == 1 {
+            name.to_string()
+        } else {
+            format!("{name}{count}{counter}")
+        }
+    }).collect::<Vec<_>>().join(" ")
+}
+
+/// 辞書と長文の設定を適用して読み上げる文章にする
+async fn reading_text(ctx: &Context, guild_id: GuildId, prefix: &str, content: &str) -> String {
+    match config::guild_config(ctx, guild_id).await {
+        Ok(guild_config) => guild_config.read().await.reading_text(prefix, content),
+        Err(_) => format!("{prefix}{}", content.replace('\n', "、"))
+    }
+}
+
+/// ギルドの辞書を適用する
+async fn apply_dictionary(ctx: &Context, guild_id: GuildId, text: &str) -> String {
+    let Ok(guild_config) = config::guild_config(ctx, guild_id).await else {
+        return text.to_string();
+    };
+    let dict = &guild_config.read().await.dictionary;
+    dict.apply(text).unwrap_or(text.to_string())
+}
+
+/// 自動接続のルールに該当するボイスチャンネルに入室したユーザーがいれば接続する
+async fn auto_join(ctx: &Context, guild_id: GuildId, old: Option<&VoiceState>, new: &VoiceState) {
+    let Some(voice_channel) = new.channel_id else { return; };
+    if old.and_then(|state| state.channel_id) == Some(voice_channel) {
+        return;
+    }
+    if new.member.as_ref().is_some_and(|member| member.user.bot) {
+        return;
+    }
+
+    let Ok(guild_config) = config::guild_config(ctx, guild_id).await else { return; };
+    let Some(text_channel) = ({
+        let auto_join = &guild_config.read().await.auto_join;
+        auto_join.find(voice_channel, new.user_id).map(|rule| rule.text_channel)
+    }) else { return; };
+
+    info!(guild_id = %guild_id, voice_channel = %voice_channel, "auto join");
+    if let Err(why) = session::connect(ctx, guild_id, voice_channel, text_channel).await {
+        error!("Failed to auto join: {why}");
+    }
+}
+
+/// ボイスチャンネルへの入退室などを読み上げる
+async fn announce_voice_state(ctx: &Context, guild_id: GuildId, voice_channel: ChannelId, old: Option<&VoiceState>, new: &VoiceState) {
+    if new.member.as_ref().is_some_and(|member| member.user.bot) {
+        return;
+    }
+
+    let old_channel = old.and_then(|state| state.channel_id);
+    let was_streaming = old.and_then(|state| state.self_stream) == Some(true);
+
+    let Ok(guild_config) = config::guild_config(ctx, guild_id).await else { return; };
+    let Some(announcement) = ({
+        let config = guild_config.read().await;
+        let roles = new.member.as_ref().map_or(&[][..], |member| &member.roles);
+        let announce = &config.voice_announce;
+        let announcement = if old_channel != Some(voice_channel) && new.channel_id == Some(voice_channel) {
+            Some(&announce.join)
+        } else if old_channel == Some(voice_channel) && new.channel_id.is_none() {
+            Some(&announce.leave)
+        } else if old_channel == Some(voice_channel) && new.channel_id != Some(voice_channel) {
+            Some(&announce.move_channel)
+        } else if new.channel_id == Some(voice_channel) && !was_streaming && new.self_stream == Some(true) {
+            Some(&announce.stream)
+        } else {
+            None
+        };
+        announcement
+            .filter(|announcement| announcement.enabled)
+            .filter(|_| !config.ignore.is_ignored(new.user_id, roles, ""))
+            .cloned()
+    }) else { return; };
+
+    let name = match &new.member {
+        Some(member) => member.display_name().into_owned(),
+        None => match new.user_id.to_user(ctx).await {
+            Ok(user) => user.name,
+            Err(_) => return
+        }
+    };
+    let channel = match new.channel_id {
+        Some(channel_id) => channel_id.name(&ctx.cache).await.unwrap_or_default(),
+        None => String::new()
+    };
+
+    let text = apply_dictionary(ctx, guild_id, &announcement.render(&name, &channel)).await;
+    let _ = speak(ctx, guild_id, text.trim(), TrackInfo::announcement()).await;
+}
+
+/// ボイスチャンネルにbot以外のメンバーがいないか判定する
+async fn is_empty_channel(ctx: &Context, voice_channel: ChannelId) -> bool {
+    let Ok(Channel::Guild(channel)) = voice_channel.to_channel(&ctx.http).await else { return false; };
+    let Ok(members) = channel.members(&ctx.cache).await else { return false; };
+    members.iter().all(|member| member.user.bot)
+}
+
+/// 設定に従ってお知らせを読み上げてからボイスチャンネルから切断する
+async fn auto_disconnect(ctx: &Context, guild_id: GuildId) {
+    let message = match config::guild_config(ctx, guild_id).await {
+        Ok(guild_config) => {
+            let config = &guild_config.read().await.auto_disconnect;
+            Some(config.message.clone()).filter(|message| config.announce && !message.trim().is_empty())
+        },
+        Err(_) => None
+    };
+
+    if let Some(message) = message {
+        // 読み上げ待ちのメッセージを破棄してすぐにお知らせを読み上げる
+        if let Some(handle) = songbird::get(ctx).await.and_then(|manager| manager.get(guild_id)) {
+            handle.lock().await.queue().stop();
+        }
+        let text = apply_dictionary(ctx, guild_id, &message).await;
+        if let Ok(track) = speak(ctx, guild_id, text.trim(), TrackInfo::announcement()).await {
+            // 一時停止中などで読み上げが終わらない場合に備えて最大30秒まで待つ
+            for _ in 0..60 {
+                match track.get_info().await {
+                    Ok(state) if !state.playing.is_done() => {
+                        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+                    },
+                    _ => break
+                }
+            }
+        }
+    }
+
+    if let Err(why) = session::disconnect(ctx, guild_id).await {
+        error!("Failed to disconnect: {why}");
+    }
+}
+
+/// 一定時間メッセージを読み上げていないボイスチャンネルから切断する
+async fn disconnect_idle(ctx: &Arc<Context>) {
+    for (guild_id, session) in session::all(ctx).await {
+        let Ok(guild_config) = config::guild_config(ctx, guild_id).await else { continue; };
+        let idle_minutes = {
+            let config = &guild_config.read().await.auto_disconnect;
+            if config.stay { 0 } else { config.idle_minutes }
+        };
+        if idle_minutes == 0 || session.last_activity.elapsed().as_secs() < idle_minutes * 60 {
+            continue;
+        }
+        info!(guild_id = %guild_id, "idle disconnect");
+        let ctx = Arc::clone(ctx);
+        tokio::spawn(async move {
+            auto_disconnect(&ctx, guild_id).await;
+        });
+    }
+}
+
+/// 編集前後のテキストを比較して、編集後のテキストのうち変更された部分を返す
+///
+/// 英単語の途中で切れないように、変更箇所の前後の英数字も含める。
+fn edited_part<'a>(old: &str, new: &'a str) -> &'a str {
+    let prefix = old.chars()
+        .zip(new.chars())
+        .take_while(|(a, b)| a == b)
+        .map(|(_, c)| c.len_utf8())
+        .sum::<usize>();
+    let suffix = old[prefix..].chars().rev()
+        .zip(new[prefix..].chars().rev())
+        .take_while(|(a, b)| a == b)
+        .map(|(_, c)| c.len_utf8())
+        .sum::<usize>();
+    // 文字が削除されただけの場合
+    if prefix + suffix == new.len() {
+        return "";
+    }
+
+    let start = new[..prefix]
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| c.is_ascii_alphanumeric())
+        .last()
+        .map_or(prefix, |(i, _)| i);
+    let end = new.len() - suffix;
+    let end = end + new[end..]
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.len_utf8())
+        .sum::<usize>();
+
+    &new[start..end]
+}
+
+/// 読み上げ待ちの音声のうち、指定したメッセージのものをキューから取り除く
+///
+/// 再生中の音声は取り除かない。
+async fn cancel_messages(ctx: &Context, guild_id: GuildId, message_ids: &[MessageId]) -> Result<()> {
+    let is_enabled = config::guild_config(ctx, guild_id).await?.read().await.reading.cancel_deleted;
+    if !is_enabled {
+        return Ok(());
+    }
+
+    let Some(manager) = songbird::get(ctx).await else {
+        anyhow::bail!("Failed to retrieve Songbird voice client");
+    };
+    let Some(handle) = manager.get(guild_id) else {
+        anyhow::bail!("Failed to retrieve Call handler");
+    };
+    let queue = handle.lock().await.queue().clone();
+
+    for track in queue.current_queue().into_iter().skip(1) {
+        let is_target = track.typemap().read().await.get::<TrackInfo>()
+            .is_some_and(|info| info.message_ids.iter().any(|id| message_ids.contains(id)));
+        if !is_target {
+            continue;
+        }
+        let removed = queue.modify_queue(|q| {
+            match q.iter().position(|queued| queued.uuid() == track.uuid()) {
+                Some(index) if index > 0 => q.remove(index),
+                _ => None
+            }
+        });
+        if let Some(removed) = removed {
+            let _ = removed.stop();
+        }
+    }
+
+    Ok(())
+}
+
+/// キュー内の優先する音声を返す
+async fn priority_tracks(queue: &TrackQueue) -> Vec<TrackHandle> {
+    let mut tracks = Vec::new();
+    for queued in queue.current_queue() {
+        if queued.typemap().read().await.get::<TrackInfo>().is_some_and(|info| info.priority) {
+            tracks.push(queued);
         }
     }
+    tracks
 }
 
-async fn speak(ctx: &Context, guild_id: GuildId, text: &str) -> Result<()> {
+pub async fn speak(ctx: &Context, guild_id: GuildId, text: &str, mut info: TrackInfo) -> Result<TrackHandle> {
     let Some(manager) = songbird::get(ctx).await else {
         anyhow::bail!("Failed to retrieve Songbird voice client");
     };
     let Some(handle) = manager.get(guild_id) else {
         anyhow::bail!("Failed to retrieve Call handler");
     };
-    let speaker_id = {
-        let data_read = ctx.data.read().await;
-        let config = data_read.get::<ConfigData>().unwrap();
-        let mut config_lock = config.lock().unwrap();
-        config_lock.guild_config(guild_id).speaker_id
+    let guild_config = config::guild_config(ctx, guild_id).await?;
+    let (speaker_id, length, flood) = {
+        let config = guild_config.read().await;
+        (config.voice.speaker_id, config.length.clone(), config.flood.clone())
+    };
+    let max_queue_secs = length.max_queue_secs;
+    let queue = handle.lock().await.queue().clone();
+
+    let mut coalesce = None;
+    if let (Some(author), false) = (info.author, info.priority) {
+        // ユーザーごとの読み上げ回数の制限
+        let is_allowed = {
+            let data_read = ctx.data.read().await;
+            let rate_limit = data_read.get::<RateLimit>().unwrap();
+            let mut limiter = rate_limit.lock().unwrap();
+            limiter.check(guild_id, author, flood.max_per_minute, std::time::Instant::now())
+        };
+        if !is_allowed {
+            anyhow::bail!("Rate limited");
+        }
+
+        // 同じユーザーの読み上げ待ちのメッセージとまとめる
+        if flood.coalesce {
+            if let Some(last) = queue.current_queue().into_iter().skip(1).last() {
+                let last_info = last.typemap().read().await.get::<TrackInfo>().cloned().unwrap_or_default();
+                if last_info.author == Some(author) {
+                    coalesce = Some((last, last_info));
+                }
+            }
+        }
+    }
+
+    // まとめた文章は合成と上限の確認ができてから前のメッセージと置き換える
+    let prepare = |text: &str, replaced: Option<&TrackHandle>| -> Result<(Input, std::time::Duration)> {
+        let Ok(data) = synthesis::synthesis(text, speaker_id) else {
+            anyhow::bail!("Failed to synthesis");
+        };
+        let input = synthesis::to_input(&data);
+        let duration = input.metadata.duration.unwrap_or_default();
+        if let Some(replaced) = replaced {
+            let tracks = queue.current_queue().into_iter()
+                .filter(|track| track.uuid() != replaced.uuid())
+                .collect::<Vec<_>>();
+            if is_over_limit(&tracks, duration, flood.max_pending, max_queue_secs) {
+                anyhow::bail!("Too many queued tracks");
+            }
+        }
+        Ok((input, duration))
     };
-    let Ok(data) = synthesis::synthesis(text, speaker_id) else {
-        anyhow::bail!("Failed to synthesis");
+    let new_text = text;
+    let merged = coalesce.as_ref().map(|(_, last_info)| length.apply(&format!("{} {new_text}", last_info.text)));
+    let (mut text, (mut input, mut duration), coalesced) = coalesce_or_new(
+        merged.as_deref(),
+        new_text,
+        |text, merged| prepare(text, coalesce.as_ref().map(|(last, _)| last).filter(|_| merged))
+    )?;
+    if coalesced {
+        let (last, last_info) = coalesce.unwrap();
+        let removed = queue.modify_queue(|q| {
+            match q.iter().position(|queued| queued.uuid() == last.uuid()) {
+                Some(index) if index > 0 => q.remove(index).is_some(),
+                _ => false
+            }
+        });
+        if removed {
+            let _ = last.stop();
+            info.message_ids = [last_info.message_ids, info.message_ids].concat();
+        } else {
+            // 合成している間に前のメッセージの再生が始まった
+            text = new_text.to_string();
+            (input, duration) = prepare(&text, None)?;
+        }
+    }
+
+    // 読み上げ待ちが上限を超える場合は古いものから破棄するか、新しいものを追加しない
+    if !info.priority {
+        let is_over = |tracks: &[TrackHandle]| is_over_limit(tracks, duration, flood.max_pending, max_queue_secs);
+        if flood.drop_oldest {
+            let priority_tracks = priority_tracks(&queue).await;
+            loop {
+                let tracks = queue.current_queue();
+                if !is_over(&tracks) {
+                    break;
+                }
+                let Some(oldest) = tracks.iter().skip(1).find(|track| !priority_tracks.iter().any(|p| p.uuid() == track.uuid())) else {
+                    anyhow::bail!("Too many queued tracks");
+                };
+                queue.modify_queue(|q| {
+                    if let Some(index) = q.iter().position(|queued| queued.uuid() == oldest.uuid()).filter(|&i| i > 0) {
+                        q.remove(index);
+                    }
+                });
+                let _ = oldest.stop();
+            }
+        } else if is_over(&queue.current_queue()) {
+            anyhow::bail!("Too many queued tracks");
+        }
+    }
+
+    let track = {
+        let mut handler = handle.lock().await;
+        handler.enqueue_source(input)
     };
-    let input = synthesis::to_input(&data);
-    let mut handler = handle.lock().await;
-    handler.enqueue_source(input);
+    let priority = info.priority;
+    track.typemap().write().await.insert::<TrackInfo>(TrackInfo { text, ..info });
+    if !priority {
+        session::touch(ctx, guild_id).await;
+    }
 
-    Ok(())
+    // 優先する音声は再生中の音声の直後(他の優先する音声がある場合はその後ろ)に移動する
+    if priority {
+        let priority_tracks = priority_tracks(&queue).await;
+        queue.modify_queue(|q| {
+            let Some(index) = q.iter().position(|queued| queued.uuid() == track.uuid()) else { return; };
+            if index <= 1 {
+                return;
+            }
+            let queued = q.remove(index).unwrap();
+            let position = 1 + q.iter()
+                .skip(1)
+                .take_while(|queued| priority_tracks.iter().any(|p| p.uuid() == queued.uuid()))
+                .count();
+            q.insert(position, queued);
+        });
+    }
+
+    Ok(track)
+}
+
+/// 読み上げ待ちの数か合計の長さが上限を超えるか。`tracks`の先頭は再生中の音声
+fn is_over_limit(tracks: &[TrackHandle], duration: std::time::Duration, max_pending: usize, max_queue_secs: u64) -> bool {
+    let pending = tracks.len().saturating_sub(1);
+    let queued = tracks.iter()
+        .filter_map(|track| track.metadata().duration)
+        .sum::<std::time::Duration>() + duration;
+    (max_pending > 0 && pending >= max_pending)
+        || (max_queue_secs > 0 && queued.as_secs() > max_queue_secs)
+}
+
+/// まとめた文章で準備できなかった場合は新しい文章だけで準備する。まとめたかどうかも返す
+fn coalesce_or_new<T>(merged: Option<&str>, text: &str, mut prepare: impl FnMut(&str, bool) -> Result<T>) -> Result<(String, T, bool)> {
+    if let Some(merged) = merged {
+        if let Ok(prepared) = prepare(merged, true) {
+            return Ok((merged.to_string(), prepared, true));
+        }
+    }
+    Ok((text.to_string(), prepare(text, false)?, false))
 }
 
 fn time_message() -> String {
@@ -275,3 +892,35 @@ fn time_message() -> String {
         local_hour % 12
     )
 }
+
+#[test]
+fn test_edited_part() {
+    assert_eq!(edited_part("こんにちは", "こんばんは"), "ばん");
+    assert_eq!(edited_part("hello world", "hello wonderful world"), "wonderful world");
+    assert_eq!(edited_part("hello world", "hello worlds"), "worlds");
+    assert_eq!(edited_part("abc", "abc"), "");
+    assert_eq!(edited_part("hello big world", "hello world"), "");
+}
+
+#[test]
+fn test_describe_attachments() {
+    let kinds = [
+        AttachmentKind::new(Some("image/png"), "a.png"),
+        AttachmentKind::new(None, "b.JPG"),
+        AttachmentKind::new(Some("application/pdf"), "c.pdf"),
+        AttachmentKind::new(None, "d.zip")
+    ];
+    assert_eq!(describe_attachments(kinds.into_iter()), "画像2枚 PDFファイル 添付ファイル");
+}
+
+#[test]
+fn test_coalesce_or_new() {
+    let prepare = |max_len: usize| move |text: &str, _| {
+        if text.chars().count() > max_len { anyhow::bail!("Too many queued tracks") } else { Ok(text.len()) }
+    };
+    let result = |result: Result<(String, usize, bool)>| result.map(|(text, _, coalesced)| (text, coalesced)).ok();
+    assert_eq!(result(coalesce_or_new(Some("前の文 次の文"), "次の文", prepare(10))), Some(("前の文 次の文".into(), true)));
+    assert_eq!(result(coalesce_or_new(Some("前の文 次の文"), "次の文", prepare(5))), Some(("次の文".into(), false)));
+    assert_eq!(result(coalesce_or_new(Some("前の文 次の文"), "次の文", prepare(2))), None);
+    assert_eq!(result(coalesce_or_new(None, "次の文", prepare(10))), Some(("次の文".into(), false)));
+}
@@ -1,32 +1,427 @@
-use std::io::Write;
-use std::fs::File;
-use std::path::Path;
-use chrono::Datelike;
-
-fn open_log_file() -> std::io::Result<File> {
-    let log_dir = Path::new("logs");
-    if !log_dir.exists() {
-        std::fs::create_dir(log_dir).unwrap();
-    }
-    let now = chrono::Utc::now().with_timezone(&chrono_tz::Japan);
-    let filename = format!("{}-{:02}-{:02}.log", now.year(), now.month(), now.day());
-    std::fs::OpenOptions::new()
-        .write(true)
+use crate::config::{LogConfig, LogFormat};
+use std::io::{self, BufRead, BufReader, Write};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::collections::VecDeque;
+use chrono::{DateTime, Days, NaiveDate};
+use chrono_tz::Tz;
+use anyhow::{Context as _, Result};
+use once_cell::sync::OnceCell;
+use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
+use serde_json::Value;
+use tracing::{error, warn, Level};
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::fmt::{format::Writer, time::FormatTime};
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+pub const LOG_DIR: &str = "logs";
+
+static FILTER: OnceCell<reload::Handle<EnvFilter, Registry>> = OnceCell::new();
+static OUTPUT: OnceCell<Mutex<Output>> = OnceCell::new();
+/// 圧縮と削除を同時に実行しないためのロック
+static MAINTENANCE: Mutex<()> = Mutex::new(());
+
+/// `logs/`のファイル
+///
+/// 書き込み中のファイルは`YYYY-MM-DD.log`で、サイズで切り替えたファイルは`YYYY-MM-DD.N.log`になる。
+/// 圧縮したファイルは`.gz`が付く。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogFile {
+    pub path: PathBuf,
+    pub date: NaiveDate,
+    /// サイズで切り替えたファイルの番号。日付ごとの最後のファイルは`None`
+    pub part: Option<u32>,
+    pub compressed: bool
+}
+
+impl LogFile {
+    fn parse(path: PathBuf) -> Option<Self> {
+        let name = path.file_name()?.to_str()?;
+        let (name, compressed) = match name.strip_suffix(".gz") {
+            Some(name) => (name, true),
+            None => (name, false)
+        };
+        let name = name.strip_suffix(".log")?;
+        let (date, part) = match name.split_once('.') {
+            Some((date, part)) => (date, Some(part.parse().ok()?)),
+            None => (name, None)
+        };
+        let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?;
+        Some(Self { path, date, part, compressed })
+    }
+
+    /// 圧縮されている場合は展開して読む
+    pub fn open(&self) -> io::Result<Box<dyn BufRead>> {
+        match File::open(&self.path) {
+            Ok(file) if self.compressed => Ok(Box::new(BufReader::new(MultiGzDecoder::new(file)))),
+            Ok(file) => Ok(Box::new(BufReader::new(file))),
+            // 一覧を取得したあとに圧縮された
+            Err(why) if why.kind() == io::ErrorKind::NotFound && !self.compressed => {
+                let file = File::open(self.path.with_extension("log.gz"))?;
+                Ok(Box::new(BufReader::new(MultiGzDecoder::new(file))))
+            },
+            Err(why) => Err(why)
+        }
+    }
+}
+
+/// `logs/`のファイルを古い順に並べる
+pub fn files() -> io::Result<Vec<LogFile>> {
+    let dir = Path::new(LOG_DIR);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut files = fs::read_dir(dir)?
+        .map(|entry| Ok(LogFile::parse(entry?.path())))
+        .filter_map(Result::transpose)
+        .collect::<io::Result<Vec<_>>>()?;
+    files.sort_by_key(|file| (file.date, file.part.unwrap_or(u32::MAX)));
+    Ok(files)
+}
+
+/// ログを絞り込む条件
+#[derive(Debug, Clone)]
+pub struct LogQuery {
+    pub from: DateTime<Tz>,
+    pub to: DateTime<Tz>,
+    /// この重要度以上の行
+    pub level: Option<Level>,
+    pub guild_id: Option<u64>,
+    /// 実行中のスラッシュコマンドの名前
+    pub command: Option<String>,
+    /// 条件に一致する最後の行数
+    pub tail: Option<usize>
+}
+
+impl LogQuery {
+    /// 期間内のファイルを古い順に読んで、条件に一致する行を`out`に書き込む。書き込んだ行数を返す
+    ///
+    /// `tail`を指定した場合のみ、最後の行数分をメモリに保持する。
+    pub fn run(&self, out: &mut impl Write) -> io::Result<usize> {
+        let (from, to) = (self.from.date_naive(), self.to.date_naive());
+        let mut tail = VecDeque::new();
+        let mut count = 0;
+        for file in files()?.iter().filter(|file| from <= file.date && file.date <= to) {
+            for line in file.open()?.lines() {
+                let line = line?;
+                if !self.matches(&line) {
+                    continue;
+                }
+                match self.tail {
+                    Some(max) => {
+                        tail.push_back(line);
+                        if tail.len() > max {
+                            tail.pop_front();
+                        }
+                    },
+                    None => {
+                        writeln!(out, "{line}")?;
+                        count += 1;
+                    }
+                }
+            }
+        }
+        for line in &tail {
+            writeln!(out, "{line}")?;
+        }
+        Ok(count + tail.len())
+    }
+
+    fn matches(&self, line: &str) -> bool {
+        match serde_json::from_str::<Value>(line) {
+            Ok(entry) if entry.is_object() => self.matches_json(&entry),
+            _ => self.matches_text(line)
+        }
+    }
+
+    /// ギルドとコマンドはイベントと親のスパンのフィールドから探す
+    fn matches_json(&self, entry: &Value) -> bool {
+        let time = entry["timestamp"].as_str().and_then(|time| DateTime::parse_from_rfc3339(time).ok());
+        let level = entry["level"].as_str().and_then(|level| level.parse().ok());
+        let field = |name: &str| {
+            std::iter::once(&entry["fields"])
+                .chain(entry["spans"].as_array().into_iter().flatten())
+                .filter_map(|fields| fields.get(name))
+                .map(|value| value.as_str().map_or(value.to_string(), str::to_string))
+                .collect::<Vec<_>>()
+        };
+        self.matches_time_and_level(time, level)
+            && self.guild_id.is_none_or(|guild_id| field("guild_id").contains(&guild_id.to_string()))
+            && self.command.as_ref().is_none_or(|command| field("command").contains(command))
+    }
+
+    /// JSON以外の形式では時刻と重要度を行頭から読み取り、ギルドとコマンドは行に含まれるかで判断する
+    fn matches_text(&self, line: &str) -> bool {
+        let mut words = line.split_whitespace();
+        let time = words.next().and_then(|time| DateTime::parse_from_rfc3339(time).ok());
+        let level = words.next().and_then(|level| level.parse().ok());
+        self.matches_time_and_level(time, level)
+            && self.guild_id.is_none_or(|guild_id| line.contains(&guild_id.to_string()))
+            && self.command.as_ref().is_none_or(|command| line.contains(command.as_str()))
+    }
+
+    /// 時刻と重要度が読み取れない行は除かない
+    fn matches_time_and_level(&self, time: Option<DateTime<chrono::FixedOffset>>, level: Option<Level>) -> bool {
+        time.is_none_or(|time| self.from <= time && time <= self.to)
+            && level.zip(self.level).is_none_or(|(level, min)| level <= min)
+    }
+}
+
+/// ファイルと標準エラー出力への出力先
+struct Output {
+    config: LogConfig,
+    timezone: Tz,
+    /// 書き込み中のファイルとその日付とサイズ
+    current: Option<(NaiveDate, File, u64)>
+}
+
+impl Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+        if self.config.stderr {
+            io::stderr().write_all(buf)?;
+        }
+        if !self.config.file {
+            return Ok(());
+        }
+
+        let today = today(self.timezone);
+        let max_size = self.config.max_file_mb * 1024 * 1024;
+        let expired = self.current.as_ref().is_some_and(|(date, _, _)| *date != today);
+        let full = !expired && self.current.as_ref()
+            .is_some_and(|(_, _, size)| max_size > 0 && *size > 0 && size + buf.len() as u64 > max_size);
+        if expired || full {
+            self.current = None;
+            if full {
+                rotate(today)?;
+            }
+            spawn_maintenance(self.config.clone(), today);
+        }
+
+        let (_, file, size) = match &mut self.current {
+            Some(current) => current,
+            None => self.current.insert(open(today)?)
+        };
+        file.write_all(buf)?;
+        *size += buf.len() as u64;
+        Ok(())
+    }
+}
+
+fn today(timezone: Tz) -> NaiveDate {
+    chrono::Utc::now().with_timezone(&timezone).date_naive()
+}
+
+fn open(date: NaiveDate) -> io::Result<(NaiveDate, File, u64)> {
+    fs::create_dir_all(LOG_DIR)?;
+    let file = fs::OpenOptions::new()
         .create(true)
         .append(true)
-        .open(log_dir.join(filename))
+        .open(Path::new(LOG_DIR).join(format!("{}.log", date.format("%Y-%m-%d"))))?;
+    let size = file.metadata()?.len();
+    Ok((date, file, size))
+}
+
+/// 書き込み中のファイルに番号を付けて、次の書き込みで新しいファイルを作る
+fn rotate(date: NaiveDate) -> io::Result<()> {
+    let part = files()?.iter()
+        .filter(|file| file.date == date)
+        .filter_map(|file| file.part)
+        .max()
+        .unwrap_or_default() + 1;
+    let name = date.format("%Y-%m-%d");
+    let dir = Path::new(LOG_DIR);
+    fs::rename(dir.join(format!("{name}.log")), dir.join(format!("{name}.{part}.log")))
+}
+
+fn spawn_maintenance(config: LogConfig, today: NaiveDate) {
+    std::thread::spawn(move || {
+        if let Err(why) = maintain(&config, today) {
+            error!("Failed to list log files: {why}");
+        }
+    });
+}
+
+/// 書き込みが終わったファイルを圧縮して、保存期間と合計サイズの上限を超えたファイルを古い順に削除する
+///
+/// 個別のファイルの圧縮や削除に失敗した場合は記録して、残りのファイルの処理を続ける。
+fn maintain(config: &LogConfig, today: NaiveDate) -> io::Result<()> {
+    let _guard = MAINTENANCE.lock().unwrap();
+    let is_current = |file: &LogFile| file.date == today && file.part.is_none();
+    let mut files = files()?;
+
+    if config.compress {
+        for file in files.iter_mut().filter(|file| !file.compressed && !is_current(file)) {
+            match compress(&file.path) {
+                Ok(path) => {
+                    file.path = path;
+                    file.compressed = true;
+                },
+                Err(why) => error!(path = %file.path.display(), "Failed to compress log file: {why}")
+            }
+        }
+    }
+
+    if config.retention_days > 0 {
+        let oldest = today.checked_sub_days(Days::new(config.retention_days.into())).unwrap_or(NaiveDate::MIN);
+        files.retain(|file| file.date >= oldest || !remove(&file.path));
+    }
+
+    if config.max_total_mb > 0 {
+        let max_total = config.max_total_mb * 1024 * 1024;
+        let sizes = files.iter()
+            .filter_map(|file| match fs::metadata(&file.path) {
+                Ok(metadata) => Some((file, metadata.len())),
+                Err(why) => {
+                    error!(path = %file.path.display(), "Failed to read log file size: {why}");
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        let mut total = sizes.iter().map(|(_, size)| size).sum::<u64>();
+        for (file, size) in sizes.into_iter().filter(|(file, _)| !is_current(file)) {
+            if total <= max_total {
+                break;
+            }
+            if remove(&file.path) {
+                total -= size;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 削除に失敗した場合は記録して`false`を返す
+fn remove(path: &Path) -> bool {
+    match fs::remove_file(path) {
+        Ok(()) => true,
+        Err(why) => {
+            error!(path = %path.display(), "Failed to delete log file: {why}");
+            false
+        }
+    }
+}
+
+/// `<path>.gz`に圧縮して元のファイルを削除する
+fn compress(path: &Path) -> io::Result<PathBuf> {
+    let compressed = path.with_extension("log.gz");
+    // 圧縮の途中で終了しても壊れた`.gz`を残さない
+    let tmp = path.with_extension("log.gz.tmp");
+    let mut encoder = GzEncoder::new(File::create(&tmp)?, Compression::default());
+    io::copy(&mut File::open(path)?, &mut encoder)?;
+    encoder.finish()?.sync_all()?;
+    fs::rename(&tmp, &compressed)?;
+    fs::remove_file(path)?;
+    Ok(compressed)
 }
 
 pub struct LogWriter;
 
 impl Write for LogWriter {
-    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        let mut file = open_log_file()?;
-        writeln!(file, "{}", String::from_utf8(buf.to_vec()).unwrap())?;
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(output) = OUTPUT.get() {
+            output.lock().unwrap().write(buf)?;
+        }
         Ok(buf.len())
     }
 
-    fn flush(&mut self) -> std::io::Result<()> {
+    fn flush(&mut self) -> io::Result<()> {
         Ok(())
     }
 }
+
+/// 設定したタイムゾーンのRFC 3339形式の時刻
+struct Timer(Tz);
+
+impl FormatTime for Timer {
+    fn format_time(&self, w: &mut Writer<'_>) -> std::fmt::Result {
+        write!(w, "{}", chrono::Utc::now().with_timezone(&self.0).format("%Y-%m-%dT%H:%M:%S%.3f%:z"))
+    }
+}
+
+/// ログの出力を設定する。起動時に一度だけ呼ぶ
+///
+/// 起動時に前回までのファイルの圧縮と削除も行う。手動で編集されたタイムゾーンが無効な場合は日本時間を使う。
+pub fn init(config: LogConfig) -> Result<()> {
+    let (timezone, invalid) = match config.timezone() {
+        Ok(timezone) => (timezone, None),
+        Err(why) => (chrono_tz::Japan, Some(why))
+    };
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(|| LogWriter)
+        .with_ansi(false)
+        .with_timer(Timer(timezone));
+    let layer = match config.format {
+        LogFormat::Pretty => layer.pretty().boxed(),
+        LogFormat::Compact => layer.compact().boxed(),
+        LogFormat::Json => layer.json().boxed()
+    };
+
+    if config.file {
+        spawn_maintenance(config.clone(), today(timezone));
+    }
+    let output = Output { config, timezone, current: None };
+    if OUTPUT.set(Mutex::new(output)).is_err() {
+        anyhow::bail!("log is already initialized");
+    }
+
+    tracing_subscriber::registry()
+        .with(reloadable_filter(EnvFilter::from_default_env()))
+        .with(layer)
+        .try_init()?;
+    if let Some(why) = invalid {
+        warn!("Invalid log timezone, using {timezone}: {why}");
+    }
+    Ok(())
+}
+
+/// 実行中に`set_filter`で変更できるログのフィルタ
+fn reloadable_filter(filter: EnvFilter) -> reload::Layer<EnvFilter, Registry> {
+    let (layer, handle) = reload::Layer::new(filter);
+    let _ = FILTER.set(handle);
+    layer
+}
+
+/// ログのフィルタを`RUST_LOG`と同じ形式で変更する
+pub fn set_filter(directives: &str) -> Result<()> {
+    let filter = EnvFilter::try_new(directives)?;
+    FILTER.get().context("log filter is not initialized")?.reload(filter)?;
+    Ok(())
+}
+
+#[test]
+fn test_parse_log_file() {
+    let date = NaiveDate::from_ymd_opt(2023, 9, 1).unwrap();
+    let parse = |name: &str| LogFile::parse(Path::new(LOG_DIR).join(name));
+    assert_eq!(parse("2023-09-01.log").map(|file| (file.date, file.part, file.compressed)), Some((date, None, false)));
+    assert_eq!(parse("2023-09-01.2.log.gz").map(|file| (file.date, file.part, file.compressed)), Some((date, Some(2), true)));
+    assert_eq!(parse("2023-09-01.log.gz.tmp"), None);
+    assert_eq!(parse("notes.log"), None);
+}
+
+#[test]
+fn test_log_query() {
+    let timezone = chrono_tz::Japan;
+    let time = |time: &str| NaiveDate::from_ymd_opt(2023, 9, 1).unwrap()
+        .and_time(time.parse().unwrap())
+        .and_local_timezone(timezone)
+        .unwrap();
+    let query = LogQuery {
+        from: time("12:00:00"),
+        to: time("13:00:00"),
+        level: Some(Level::INFO),
+        guild_id: Some(123),
+        command: Some("dictionary".into()),
+        tail: None
+    };
+    let line = |timestamp: &str, level: &str, guild_id: &str| format!(
+        r#"{{"timestamp":"{timestamp}","level":"{level}","fields":{{"message":"/dictionary add"}},"spans":[{{"command":"dictionary","guild_id":{guild_id},"name":"command"}}]}}"#
+    );
+    assert!(query.matches(&line("2023-09-01T12:30:00.000+09:00", "INFO", "123")));
+    assert!(query.matches(&line("2023-09-01T03:30:00.000+00:00", "WARN", "123")));
+    assert!(!query.matches(&line("2023-09-01T12:30:00.000+09:00", "DEBUG", "123")));
+    assert!(!query.matches(&line("2023-09-01T13:30:00.000+09:00", "INFO", "123")));
+    assert!(!query.matches(&line("2023-09-01T12:30:00.000+09:00", "INFO", "456")));
+    assert!(query.matches("2023-09-01T12:30:00.000+09:00 ERROR command{command=dictionary guild_id=123}: zundamon: failed"));
+    assert!(!query.matches("2023-09-01T12:30:00.000+09:00 ERROR command{command=join guild_id=123}: zundamon: failed"));
+}
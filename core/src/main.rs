@@ -5,47 +5,44 @@ mod log;
 mod event_handler;
 mod type_map;
 mod opt;
+mod rate_limit;
+mod session;
+mod driver_events;
+mod storage;
+mod console;
 
 use config::Config;
 use event_handler::Handler;
-use type_map::{TextChannelId, ConfigData, ConnectedChannel};
+use type_map::{ConfigData, VoiceSession, RateLimit};
 use std::sync::{Arc, Mutex};
 use std::collections::HashMap;
 use tokio::io::AsyncBufReadExt;
 use tracing::{error, info};
-use tracing_subscriber::prelude::*;
-use tracing_subscriber::EnvFilter;
-use tracing_subscriber::fmt::time::OffsetTime;
 use serenity::prelude::*;
 use structopt::StructOpt;
 
-#[allow(clippy::single_match)]
 #[tokio::main]
 async fn main() {
     let opt = opt::Opt::from_args();
+    storage::init(opt.storage).expect("Failed to open storage");
     // サブコマンド
     if let Some(cmd) = opt.cmd {
         match cmd {
             opt::Command::Config(config_opt) => {
                 opt::config(config_opt).unwrap();
+            },
+            opt::Command::Say(say_opt) => {
+                opt::say(say_opt).unwrap();
+            },
+            opt::Command::Dict(dict_opt) => {
+                opt::dict(dict_opt).unwrap();
             }
         }
         return;
     }
 
-    let layer = tracing_subscriber::fmt::layer()
-        .map_writer(|_| || log::LogWriter)
-        .with_ansi(false)
-        .with_timer(
-            OffsetTime::new(time::macros::offset!(+9),
-            time::macros::format_description!("[hour]:[minute]:[second]"))
-        )
-        .json()
-        .pretty();
-    tracing_subscriber::registry()
-        .with(layer)
-        .with(EnvFilter::from_default_env())
-        .init();
+    let log_config = config::GlobalConfig::load().map(|config| config.log).unwrap_or_default();
+    log::init(log_config).expect("Failed to initialize log");
 
     let token = std::env::var("DISCORD_TOKEN").expect("Expected a token in the environment");
 
@@ -53,14 +50,17 @@ async fn main() {
     let builder = Client::builder(token, GatewayIntents::non_privileged() | GatewayIntents::MESSAGE_CONTENT);
     let mut client = songbird::serenity::register_with(builder, voice.clone())
         .event_handler(Handler::default())
+        // 編集前のメッセージを参照するためにキャッシュする
+        .cache_settings(|settings| settings.max_messages(100))
         .await
         .expect("Error creating client");
 
+    let config = Arc::new(Config::load().unwrap_or_default());
     {
         let mut data = client.data.write().await;
-        data.insert::<TextChannelId>(Arc::new(Mutex::new(HashMap::default())));
-        data.insert::<ConfigData>(Arc::new(Mutex::new(Config::load().unwrap_or_default())));
-        data.insert::<ConnectedChannel>(Arc::new(Mutex::new(HashMap::new())));
+        data.insert::<ConfigData>(config.clone());
+        data.insert::<VoiceSession>(Arc::new(Mutex::new(HashMap::new())));
+        data.insert::<RateLimit>(Arc::new(Mutex::new(Default::default())));
     }
 
     let (tx_stdout, mut rx_stdout) = tokio::sync::mpsc::unbounded_channel::<String>();
@@ -75,15 +75,17 @@ async fn main() {
         }
     });
 
-    // 標準入力をチャネルから受け取ってexitを処理する
+    // 標準入力をチャネルから受け取ってコマンドを処理する
     tokio::spawn(async move {
         while let Some(line) = rx_stdout.recv().await {
-            match line.as_str() {
-                "stop" => {
+            match console::parse(&line) {
+                Ok(Some(console::Command::Stop)) => {
                     tx_exit.send(()).unwrap();
                     break;
                 },
-                _ => {}
+                Ok(Some(command)) => console::execute(command, &config).await,
+                Ok(None) => {},
+                Err(msg) => println!("{msg}")
             }
         }
     });
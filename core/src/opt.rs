@@ -1,17 +1,28 @@
-use crate::config::GlobalConfig;
+use crate::config::{GlobalConfig, GuildConfig, LogFormat};
+use crate::storage::StorageKind;
+use crate::synthesis;
+use dictionary::{Dictionary, DictItem};
+use std::path::PathBuf;
 use anyhow::Result;
-use serenity::model::prelude::UserId;
+use serenity::model::prelude::{UserId, GuildId};
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
 pub struct Opt {
+    /// Where to store config and dictionaries (json or sqlite)
+    #[structopt(long, default_value = "json")]
+    pub storage: StorageKind,
     #[structopt(subcommand)]
     pub cmd: Option<Command>
 }
 
 #[derive(Debug, StructOpt)]
 pub enum Command {
-    Config(ConfigOpt)
+    Config(ConfigOpt),
+    /// Synthesize text to a WAV file with a guild's dictionary and settings, without Discord
+    Say(SayOpt),
+    /// Test and edit a guild's dictionary without Discord
+    Dict(DictOpt)
 }
 
 #[derive(Debug, StructOpt)]
@@ -21,24 +32,182 @@ pub struct ConfigOpt {
     pub add_admin: Option<UserId>,
     /// Remove ID of the user to have administrative privileges
     #[structopt(short, long)]
-    pub remove_admin: Option<UserId>
+    pub remove_admin: Option<UserId>,
+    /// Register slash commands globally instead of per guild (true/false)
+    #[structopt(long)]
+    pub global_commands: Option<bool>,
+    /// Log format (pretty, compact or json)
+    #[structopt(long)]
+    pub log_format: Option<LogFormat>,
+    /// Timezone of log timestamps and file dates (e.g. Asia/Tokyo)
+    #[structopt(long)]
+    pub log_timezone: Option<String>,
+    /// Write logs to files in `logs/` (true/false)
+    #[structopt(long)]
+    pub log_file: Option<bool>,
+    /// Also write logs to stderr (true/false)
+    #[structopt(long)]
+    pub log_stderr: Option<bool>,
+    /// Start a new log file when the current one exceeds this size in MB (0 for no limit)
+    #[structopt(long)]
+    pub log_max_file_mb: Option<u64>,
+    /// Delete log files older than this many days (0 to keep forever)
+    #[structopt(long)]
+    pub log_retention_days: Option<u32>,
+    /// Delete the oldest log files when the total size exceeds this in MB (0 for no limit)
+    #[structopt(long)]
+    pub log_max_total_mb: Option<u64>,
+    /// Compress finished log files with gzip (true/false)
+    #[structopt(long)]
+    pub log_compress: Option<bool>
 }
 
+#[derive(Debug, StructOpt)]
+pub struct SayOpt {
+    /// Speaker ID (defaults to the guild's speaker)
+    #[structopt(long)]
+    pub speaker: Option<u32>,
+    /// ID of the guild whose dictionary and settings are applied
+    #[structopt(long)]
+    pub guild: Option<u64>,
+    /// Output WAV file
+    #[structopt(short, long, default_value = "out.wav", parse(from_os_str))]
+    pub output: PathBuf,
+    /// Text to read out
+    pub text: String
+}
+
+#[derive(Debug, StructOpt)]
+pub struct DictOpt {
+    /// ID of the guild whose dictionary is used
+    #[structopt(long)]
+    pub guild: u64,
+    #[structopt(subcommand)]
+    pub cmd: DictCommand
+}
+
+#[derive(Debug, StructOpt)]
+pub enum DictCommand {
+    /// Print the text after applying the dictionary
+    Apply {
+        /// Text to apply the dictionary to
+        text: String
+    },
+    /// Write the dictionary as JSON (same format as `/dictionary export`)
+    Export {
+        /// Output file (defaults to stdout)
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>
+    },
+    /// Add entries from a JSON file (same format as `/dictionary import`)
+    Import {
+        /// JSON file to import
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+        /// Replace the whole dictionary instead of merging
+        #[structopt(long)]
+        replace: bool
+    },
+    /// Check the dictionary for invalid regexes, shadowed entries, chained replacements and empty readings
+    Lint
+}
 
 pub fn config(opt: ConfigOpt) -> Result<()> {
     let mut global_config = GlobalConfig::load()?;
 
     if let Some(admin) = opt.add_admin {
-        if !global_config.admin_user.contains(&admin) {
-            global_config.admin_user.push(admin);
-        }
+        global_config.add_admin(admin);
     }
 
     if let Some(admin) = opt.remove_admin {
-        if let Some(index) = global_config.admin_user.iter().position(|&user| user == admin) {
-            global_config.admin_user.swap_remove(index);
-        }
+        global_config.remove_admin(admin);
+    }
+
+    if let Some(global_commands) = opt.global_commands {
+        global_config.global_commands = global_commands;
+    }
+
+    let log = &mut global_config.log;
+    if let Some(format) = opt.log_format {
+        log.format = format;
+    }
+    if let Some(timezone) = opt.log_timezone {
+        log.timezone = timezone;
+        log.timezone()?;
+    }
+    if let Some(file) = opt.log_file {
+        log.file = file;
+    }
+    if let Some(stderr) = opt.log_stderr {
+        log.stderr = stderr;
+    }
+    if let Some(max_file_mb) = opt.log_max_file_mb {
+        log.max_file_mb = max_file_mb;
+    }
+    if let Some(retention_days) = opt.log_retention_days {
+        log.retention_days = retention_days;
+    }
+    if let Some(max_total_mb) = opt.log_max_total_mb {
+        log.max_total_mb = max_total_mb;
+    }
+    if let Some(compress) = opt.log_compress {
+        log.compress = compress;
     }
 
     global_config.save()
 }
+
+pub fn say(opt: SayOpt) -> Result<()> {
+    let config = match opt.guild {
+        Some(guild_id) => GuildConfig::load(GuildId(guild_id))?,
+        None => GuildConfig::default()
+    };
+    let text = config.reading_text("", &opt.text);
+    let text = text.trim();
+    let speaker_id = opt.speaker.unwrap_or(config.voice.speaker_id);
+    println!("{text}");
+
+    let wav = synthesis::synthesis(text, speaker_id)
+        .map_err(|code| anyhow::anyhow!("Failed to synthesis: {code:?}"))?;
+    std::fs::write(&opt.output, wav)?;
+    Ok(())
+}
+
+/// 辞書を編集した場合は保存する。実行中のbotにはコンソールの`reload`で反映する
+pub fn dict(opt: DictOpt) -> Result<()> {
+    let guild_id = GuildId(opt.guild);
+    let mut config = GuildConfig::load(guild_id)?;
+    match opt.cmd {
+        DictCommand::Apply { text } => println!("{}", config.dictionary.apply(&text)?),
+        DictCommand::Export { output } => {
+            let json = serde_json::to_string_pretty(&config.dictionary)?;
+            match output {
+                Some(output) => std::fs::write(output, json)?,
+                None => println!("{json}")
+            }
+        },
+        DictCommand::Import { input, replace } => {
+            let items: Vec<DictItem> = serde_json::from_str(&std::fs::read_to_string(input)?)?;
+            let count = items.len();
+            if replace {
+                config.dictionary = Dictionary::from_items(items);
+            } else {
+                config.dictionary.extend(items);
+            }
+            config.dictionary.validate()?;
+            config.save(guild_id)?;
+            println!("Imported {count} entries ({} total)", config.dictionary.len());
+        },
+        DictCommand::Lint => {
+            let lints = config.dictionary.lint();
+            for lint in &lints {
+                println!("{} {lint}", if lint.is_error() {"error:"} else {"warning:"});
+            }
+            if lints.iter().any(|lint| lint.is_error()) {
+                anyhow::bail!("{} problems found in {} entries", lints.len(), config.dictionary.len());
+            }
+            println!("{} problems found in {} entries", lints.len(), config.dictionary.len());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,46 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+use serenity::model::id::{GuildId, UserId};
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// ユーザーごとに直近1分間の読み上げ回数を数える
+#[derive(Debug, Default)]
+pub struct RateLimiter(HashMap<(GuildId, UserId), VecDeque<Instant>>);
+
+impl RateLimiter {
+    /// 直近1分間の読み上げ回数が`limit`未満であれば記録して`true`を返す
+    ///
+    /// `limit`が0の場合は常に`true`を返す。
+    pub fn check(&mut self, guild_id: GuildId, user_id: UserId, limit: u32, now: Instant) -> bool {
+        if limit == 0 {
+            return true;
+        }
+        for history in self.0.values_mut() {
+            while history.front().is_some_and(|&time| now.duration_since(time) >= WINDOW) {
+                history.pop_front();
+            }
+        }
+        self.0.retain(|_, history| !history.is_empty());
+
+        let history = self.0.entry((guild_id, user_id)).or_default();
+        if history.len() >= limit as usize {
+            return false;
+        }
+        history.push_back(now);
+        true
+    }
+}
+
+#[test]
+fn test_rate_limiter() {
+    let mut limiter = RateLimiter::default();
+    let (guild, user, other) = (GuildId(1), UserId(1), UserId(2));
+    let now = Instant::now();
+    assert!(limiter.check(guild, user, 2, now));
+    assert!(limiter.check(guild, user, 2, now + Duration::from_secs(10)));
+    assert!(!limiter.check(guild, user, 2, now + Duration::from_secs(20)));
+    assert!(limiter.check(guild, other, 2, now + Duration::from_secs(20)));
+    assert!(limiter.check(guild, user, 2, now + Duration::from_secs(61)));
+    assert!(limiter.check(guild, user, 0, now));
+}
@@ -0,0 +1,175 @@
+use crate::config;
+use crate::storage;
+use crate::driver_events::DriverEvents;
+use crate::type_map::VoiceSession;
+use std::collections::HashMap;
+use std::time::Instant;
+use anyhow::{Context as _, Result};
+use tracing::{error, info};
+use serde::{Serialize, Deserialize};
+use songbird::{Event, CoreEvent};
+use serenity::prelude::*;
+use serenity::model::id::{GuildId, ChannelId};
+
+pub const SESSION_FILE: &str = "sessions.json";
+
+/// ボイスチャンネルへの接続状態
+///
+/// 接続・切断のたびに設定と同じ保存先に保存され、起動時に復元される。
+/// 読み上げ待ちの上限などのキューの設定はギルドの設定に保存されるのでここには含めない。
+/// 読み上げ待ちの音声と一時停止の状態は再生中のトラックに属し、再起動すると残らないので保存しない。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Session {
+    pub voice_channel: ChannelId,
+    /// 読み上げるテキストチャンネル
+    pub text_channel: ChannelId,
+    /// 最後にメッセージを読み上げた時刻
+    #[serde(skip, default = "Instant::now")]
+    pub last_activity: Instant
+}
+
+/// 前回終了時の接続状態を読み込む
+pub fn load() -> Result<HashMap<GuildId, Session>> {
+    storage::get().load_sessions()
+}
+
+/// 現在の接続状態を保存する
+pub async fn save(ctx: &Context) -> Result<()> {
+    let sessions = {
+        let data_read = ctx.data.read().await;
+        let sessions = data_read.get::<VoiceSession>().unwrap().lock().unwrap().clone();
+        sessions
+    };
+    storage::get().save_sessions(&sessions)
+}
+
+/// ギルドの現在の接続状態を返す
+pub async fn get(ctx: &Context, guild_id: GuildId) -> Option<Session> {
+    let data_read = ctx.data.read().await;
+    let sessions = data_read.get::<VoiceSession>().unwrap();
+    let session = sessions.lock().unwrap().get(&guild_id).copied();
+    session
+}
+
+/// すべてのギルドの現在の接続状態を返す
+pub async fn all(ctx: &Context) -> Vec<(GuildId, Session)> {
+    let data_read = ctx.data.read().await;
+    let sessions = data_read.get::<VoiceSession>().unwrap();
+    let sessions = sessions.lock().unwrap().iter().map(|(&g, &s)| (g, s)).collect();
+    sessions
+}
+
+/// メッセージを読み上げた時刻を記録する
+pub async fn touch(ctx: &Context, guild_id: GuildId) {
+    let data_read = ctx.data.read().await;
+    let sessions = data_read.get::<VoiceSession>().unwrap();
+    let mut sessions = sessions.lock().unwrap();
+    if let Some(session) = sessions.get_mut(&guild_id) {
+        session.last_activity = Instant::now();
+    }
+}
+
+/// ボイスチャンネルに接続して、読み上げるテキストチャンネルを設定する
+pub async fn connect(ctx: &Context, guild_id: GuildId, voice_channel: ChannelId, text_channel: ChannelId) -> Result<()> {
+    let manager = songbird::get(ctx).await.context("Failed to retrieve Songbird voice client")?;
+    let is_new_call = manager.get(guild_id).is_none();
+    let (handle, result) = manager.join(guild_id, voice_channel).await;
+    if is_new_call {
+        let mut handler = handle.lock().await;
+        let events = DriverEvents::new(ctx, guild_id);
+        handler.add_global_event(Event::Core(CoreEvent::DriverDisconnect), events.clone());
+        handler.add_global_event(Event::Core(CoreEvent::DriverReconnect), events);
+    }
+    result?;
+
+    {
+        let data_read = ctx.data.read().await;
+        let sessions = data_read.get::<VoiceSession>().unwrap();
+        let session = Session { voice_channel, text_channel, last_activity: Instant::now() };
+        sessions.lock().unwrap().insert(guild_id, session);
+    }
+
+    save(ctx).await
+}
+
+/// 移動させられた先のボイスチャンネルを記録する
+pub async fn move_to(ctx: &Context, guild_id: GuildId, voice_channel: ChannelId) -> Result<()> {
+    {
+        let data_read = ctx.data.read().await;
+        let sessions = data_read.get::<VoiceSession>().unwrap();
+        let mut sessions = sessions.lock().unwrap();
+        if let Some(session) = sessions.get_mut(&guild_id) {
+            session.voice_channel = voice_channel;
+        }
+    }
+
+    save(ctx).await
+}
+
+/// 読み上げ待ちの音声を破棄してボイスチャンネルから切断する
+pub async fn disconnect(ctx: &Context, guild_id: GuildId) -> Result<()> {
+    let manager = songbird::get(ctx).await.context("Failed to retrieve Songbird voice client")?;
+
+    {
+        let data_read = ctx.data.read().await;
+        let sessions = data_read.get::<VoiceSession>().unwrap();
+        sessions.lock().unwrap().remove(&guild_id);
+    }
+
+    if let Some(handle) = manager.get(guild_id) {
+        let handler = handle.lock().await;
+        handler.queue().modify_queue(|q| q.clear());
+    }
+    let result = manager.leave(guild_id).await;
+
+    save(ctx).await?;
+    Ok(result?)
+}
+
+/// 前回終了時に接続していたボイスチャンネルに再接続する
+///
+/// ボイスチャンネルにbot以外のメンバーがいない場合は、24時間モードでなければ再接続しない。
+/// キャッシュのボイスチャンネルの状態を参照するので`cache_ready`以降に呼ぶ必要がある。
+pub async fn restore(ctx: &Context) {
+    let sessions = match load() {
+        Ok(sessions) => sessions,
+        Err(why) => {
+            error!("Failed to load sessions: {why}");
+            return;
+        }
+    };
+
+    for (guild_id, session) in sessions {
+        let Ok(guild_config) = config::guild_config(ctx, guild_id).await else { continue; };
+        let (rejoin, stay) = {
+            let config = guild_config.read().await;
+            (config.auto_join.rejoin_on_startup, config.auto_disconnect.stay)
+        };
+        if !rejoin {
+            continue;
+        }
+
+        let self_id = ctx.cache.current_user_id();
+        let has_members = stay || guild_id.to_guild_cached(&ctx.cache).is_some_and(|guild| {
+            guild.voice_states.values().any(|state| {
+                state.channel_id == Some(session.voice_channel)
+                    && state.user_id != self_id
+                    && !state.member.as_ref().is_some_and(|member| member.user.bot)
+            })
+        });
+        if !has_members {
+            info!(guild_id = %guild_id, "skip rejoining empty voice channel");
+            continue;
+        }
+
+        info!(guild_id = %guild_id, "rejoin");
+        if let Err(why) = connect(ctx, guild_id, session.voice_channel, session.text_channel).await {
+            error!("Failed to rejoin: {why}");
+        }
+    }
+
+    // 再接続しなかったセッションを削除する
+    if let Err(why) = save(ctx).await {
+        error!("Failed to save sessions: {why}");
+    }
+}
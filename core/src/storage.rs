@@ -0,0 +1,82 @@
+pub mod json;
+pub mod sqlite;
+
+use crate::config::{GlobalConfig, GuildConfig};
+use crate::session::Session;
+use std::str::FromStr;
+use std::collections::HashMap;
+use anyhow::Result;
+use once_cell::sync::OnceCell;
+use serenity::model::id::GuildId;
+
+static STORAGE: OnceCell<Box<dyn Storage>> = OnceCell::new();
+
+/// 設定と辞書、ボイスチャンネルへの接続状態の保存先
+pub trait Storage: Send + Sync {
+    /// 保存されていない場合はデフォルトの設定を返す
+    fn load_global(&self) -> Result<GlobalConfig>;
+
+    fn save_global(&self, config: &GlobalConfig) -> Result<()>;
+
+    /// 設定が保存されているギルド
+    fn guild_ids(&self) -> Result<Vec<GuildId>>;
+
+    /// 辞書を含むギルドの設定を読み込む。保存されていない場合はデフォルトの設定を返す
+    fn load_guild(&self, guild_id: GuildId) -> Result<GuildConfig>;
+
+    /// 辞書を含むギルドの設定を保存する
+    fn save_guild(&self, guild_id: GuildId, config: &GuildConfig) -> Result<()>;
+
+    /// 前回終了時の接続状態。保存されていない場合は空を返す
+    fn load_sessions(&self) -> Result<HashMap<GuildId, Session>>;
+
+    /// 現在の接続状態を保存する。含まれないギルドの接続状態は削除する
+    fn save_sessions(&self, sessions: &HashMap<GuildId, Session>) -> Result<()>;
+
+    /// 手動で編集された設定を読み込み直す。壊れている場合はバックアップを使わずにエラーを返す
+    fn reload_global(&self) -> Result<GlobalConfig> {
+        self.load_global()
+    }
+
+    /// 手動で編集された辞書を含むギルドの設定を読み込み直す。壊れている場合はバックアップを使わずにエラーを返す
+    fn reload_guild(&self, guild_id: GuildId) -> Result<GuildConfig> {
+        self.load_guild(guild_id)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageKind {
+    /// `config/<guild_id>/{config,dictionary}.json`と`config/sessions.json`
+    Json,
+    /// `config/zundamon.db`
+    Sqlite
+}
+
+impl FromStr for StorageKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "json" => Ok(Self::Json),
+            "sqlite" => Ok(Self::Sqlite),
+            _ => anyhow::bail!("unknown storage: {s} (expected json or sqlite)")
+        }
+    }
+}
+
+/// 保存先を設定する。起動時に一度だけ呼ぶ
+pub fn init(kind: StorageKind) -> Result<()> {
+    let storage: Box<dyn Storage> = match kind {
+        StorageKind::Json => Box::new(json::JsonStorage),
+        StorageKind::Sqlite => Box::new(sqlite::SqliteStorage::open_default()?)
+    };
+    if STORAGE.set(storage).is_err() {
+        anyhow::bail!("storage is already initialized");
+    }
+    Ok(())
+}
+
+/// 設定された保存先。設定されていない場合はJSONファイルに保存する
+pub fn get() -> &'static dyn Storage {
+    STORAGE.get_or_init(|| Box::new(json::JsonStorage)).as_ref()
+}
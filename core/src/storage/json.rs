@@ -0,0 +1,204 @@
+use crate::config::{GlobalConfig, GuildConfig, CONFIG_DIR, CONFIG_FILE, DICT_FILE, GLOBAL_CONFIG_FILE};
+use crate::session::{Session, SESSION_FILE};
+use crate::storage::Storage;
+use dictionary::Dictionary;
+use std::io::Write;
+use std::path::Path;
+use std::collections::HashMap;
+use anyhow::{Context as _, Result};
+use tracing::warn;
+use serde::{Serialize, de::DeserializeOwned};
+use serenity::model::id::GuildId;
+
+/// 保存するたびにずらしていくバックアップの数
+pub const BACKUP_COUNT: usize = 3;
+
+/// `config/<guild_id>/{config,dictionary}.json`と`config/sessions.json`に保存する
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonStorage;
+
+impl Storage for JsonStorage {
+    fn load_global(&self) -> Result<GlobalConfig> {
+        let dir = Path::new(CONFIG_DIR);
+        let config_path = dir.join(GLOBAL_CONFIG_FILE);
+        if !config_path.exists() {
+            std::fs::create_dir_all(dir)?;
+            write_json(&config_path, &GlobalConfig::default())?;
+        }
+        read_json(&config_path)
+    }
+
+    fn save_global(&self, config: &GlobalConfig) -> Result<()> {
+        write_json(&Path::new(CONFIG_DIR).join(GLOBAL_CONFIG_FILE), config)
+    }
+
+    fn guild_ids(&self) -> Result<Vec<GuildId>> {
+        let mut guild_ids = Vec::new();
+        for dir in std::fs::read_dir(CONFIG_DIR)? {
+            let dir = dir?;
+            // ギルドごとのディレクトリ以外は無視する
+            if !dir.file_type()?.is_dir() {
+                continue;
+            }
+            if let Some(guild_id) = dir.file_name().to_str().and_then(|name| name.parse().ok()) {
+                guild_ids.push(GuildId(guild_id));
+            }
+        }
+        Ok(guild_ids)
+    }
+
+    fn load_guild(&self, guild_id: GuildId) -> Result<GuildConfig> {
+        let dir = Path::new(CONFIG_DIR).join(guild_id.0.to_string());
+        let config_path = dir.join(CONFIG_FILE);
+        let dict_path = dir.join(DICT_FILE);
+        if !config_path.exists() {
+            std::fs::create_dir_all(&dir)?;
+            write_json(&config_path, &GuildConfig::default())?;
+        }
+        if !dict_path.exists() {
+            std::fs::create_dir_all(&dir)?;
+            write_json(&dict_path, &Dictionary::new())?;
+        }
+        Ok(GuildConfig {
+            dictionary: read_json(&dict_path)?,
+            ..GuildConfig::from_value(read_json(&config_path)?)?
+        })
+    }
+
+    fn save_guild(&self, guild_id: GuildId, config: &GuildConfig) -> Result<()> {
+        let dir = Path::new(CONFIG_DIR).join(guild_id.0.to_string());
+        std::fs::create_dir_all(&dir)?;
+        write_json(&dir.join(CONFIG_FILE), config)?;
+        write_json(&dir.join(DICT_FILE), &config.dictionary)?;
+        Ok(())
+    }
+
+    fn load_sessions(&self) -> Result<HashMap<GuildId, Session>> {
+        let path = Path::new(CONFIG_DIR).join(SESSION_FILE);
+        if !path.exists() {
+            return Ok(HashMap::new());
+        }
+        read_json(&path)
+    }
+
+    fn save_sessions(&self, sessions: &HashMap<GuildId, Session>) -> Result<()> {
+        std::fs::create_dir_all(CONFIG_DIR)?;
+        write_json(&Path::new(CONFIG_DIR).join(SESSION_FILE), sessions)
+    }
+
+    fn reload_global(&self) -> Result<GlobalConfig> {
+        let config_path = Path::new(CONFIG_DIR).join(GLOBAL_CONFIG_FILE);
+        if !config_path.exists() {
+            return Ok(GlobalConfig::default());
+        }
+        parse_json(&config_path)
+    }
+
+    fn reload_guild(&self, guild_id: GuildId) -> Result<GuildConfig> {
+        let dir = Path::new(CONFIG_DIR).join(guild_id.0.to_string());
+        let config_path = dir.join(CONFIG_FILE);
+        let dict_path = dir.join(DICT_FILE);
+        let config = if config_path.exists() {
+            GuildConfig::from_value(parse_json(&config_path)?)
+                .with_context(|| config_path.display().to_string())?
+        } else {
+            GuildConfig::default()
+        };
+        let dictionary = if dict_path.exists() { parse_json(&dict_path)? } else { Dictionary::new() };
+        Ok(GuildConfig { dictionary, ..config })
+    }
+}
+
+/// `path`のn世代前のバックアップのパス
+fn backup_path(path: &Path, n: usize) -> std::path::PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{n}"));
+    path.with_file_name(name)
+}
+
+/// 書き込み途中でクラッシュしても壊れないように、一時ファイルに書き込んでから置き換える
+///
+/// 置き換える前のファイルは`BACKUP_COUNT`世代までバックアップとして残す。
+/// 内容が変わらない場合は何もしない。
+pub fn write_atomic(path: &Path, contents: &str) -> Result<()> {
+    if std::fs::read_to_string(path).is_ok_and(|current| current == contents) {
+        return Ok(());
+    }
+
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+    {
+        let mut file = std::fs::File::create(&tmp_path)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+    }
+
+    if path.exists() {
+        for n in (1..BACKUP_COUNT).rev() {
+            let backup = backup_path(path, n);
+            if backup.exists() {
+                std::fs::rename(&backup, backup_path(path, n + 1))?;
+            }
+        }
+        if BACKUP_COUNT > 0 {
+            std::fs::copy(path, backup_path(path, 1))?;
+        }
+    }
+    std::fs::rename(&tmp_path, path)?;
+
+    // リネームをディスクに反映させる
+    #[cfg(unix)]
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+pub fn write_json<T: Serialize + ?Sized>(path: &Path, value: &T) -> Result<()> {
+    write_atomic(path, &format!("{}\n", serde_json::to_string_pretty(value)?))
+}
+
+/// JSONを読み込む。エラーには読み込んだファイルのパスを含める
+fn parse_json<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let data = std::fs::read_to_string(path).with_context(|| path.display().to_string())?;
+    serde_json::from_str(&data).with_context(|| path.display().to_string())
+}
+
+/// JSONを読み込む。壊れている場合は新しいバックアップから順に読み込む
+pub fn read_json<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let why = match parse_json(path) {
+        Ok(value) => return Ok(value),
+        Err(why) => why
+    };
+    for n in 1..=BACKUP_COUNT {
+        if let Ok(value) = parse_json(&backup_path(path, n)) {
+            warn!(path = %path.display(), backup = n, "Restored from backup: {why:#}");
+            // 壊れたファイルは調査できるように残しておく
+            let mut corrupt_name = path.file_name().unwrap_or_default().to_os_string();
+            corrupt_name.push(".corrupt");
+            let _ = std::fs::copy(path, path.with_file_name(corrupt_name));
+            return Ok(value);
+        }
+    }
+    Err(why)
+}
+
+#[test]
+fn test_write_atomic() {
+    let dir = std::env::temp_dir().join(format!("zundamon-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("config.json");
+    for n in 0..5 {
+        write_json(&path, &n).unwrap();
+    }
+    assert_eq!(read_json::<i32>(&path).unwrap(), 4);
+    assert_eq!(read_json::<i32>(&backup_path(&path, 1)).unwrap(), 3);
+    assert_eq!(read_json::<i32>(&backup_path(&path, BACKUP_COUNT)).unwrap(), 4 - BACKUP_COUNT as i32);
+    assert!(!backup_path(&path, BACKUP_COUNT + 1).exists());
+
+    // 壊れている場合はバックアップから読み込む
+    std::fs::write(&path, "{").unwrap();
+    assert_eq!(read_json::<i32>(&path).unwrap(), 3);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,293 @@
+use crate::config::{GlobalConfig, GuildConfig, CONFIG_DIR, GLOBAL_CONFIG_FILE};
+use crate::session::Session;
+use crate::storage::{Storage, json::JsonStorage};
+use dictionary::{Dictionary, DictItem};
+use std::path::Path;
+use std::sync::Mutex;
+use std::collections::HashMap;
+use anyhow::Result;
+use tracing::info;
+use rusqlite::{Connection, OptionalExtension, params};
+use serenity::model::id::GuildId;
+
+pub const DB_FILE: &str = "zundamon.db";
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS global_config (
+        id INTEGER PRIMARY KEY CHECK (id = 0),
+        config TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS guild_config (
+        guild_id INTEGER PRIMARY KEY,
+        config TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS dictionary (
+        guild_id INTEGER NOT NULL,
+        key TEXT NOT NULL,
+        value TEXT NOT NULL,
+        is_regex INTEGER NOT NULL,
+        position INTEGER NOT NULL DEFAULT 0,
+        PRIMARY KEY (guild_id, key)
+    );
+    CREATE TABLE IF NOT EXISTS session (
+        guild_id INTEGER PRIMARY KEY,
+        session TEXT NOT NULL
+    );
+";
+
+/// SQLiteのデータベースに保存する
+///
+/// 辞書は項目ごとに保存し、変更があった項目のみ更新する。
+pub struct SqliteStorage {
+    conn: Mutex<Connection>
+}
+
+impl SqliteStorage {
+    /// `config/zundamon.db`を開く
+    ///
+    /// データベースが空の場合はJSONファイルの設定を移行する。
+    pub fn open_default() -> Result<Self> {
+        std::fs::create_dir_all(CONFIG_DIR)?;
+        let storage = Self::open(Path::new(CONFIG_DIR).join(DB_FILE))?;
+        let has_json = Path::new(CONFIG_DIR).join(GLOBAL_CONFIG_FILE).exists() || !JsonStorage.guild_ids()?.is_empty();
+        if storage.is_empty()? && has_json {
+            storage.migrate_from(&JsonStorage)?;
+        }
+        Ok(storage)
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(SCHEMA)?;
+        // 辞書の項目は順番に適用されるので、`position`のない古いデータベースに追加する
+        let has_position: bool = conn.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('dictionary') WHERE name = 'position'",
+            [],
+            |row| row.get(0)
+        )?;
+        if !has_position {
+            conn.execute_batch("ALTER TABLE dictionary ADD COLUMN position INTEGER NOT NULL DEFAULT 0")?;
+        }
+        Ok(Self { conn: Mutex::new(conn) })
+    }
+
+    fn is_empty(&self) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let count: i64 = conn.query_row(
+            "SELECT (SELECT COUNT(*) FROM global_config) + (SELECT COUNT(*) FROM guild_config)",
+            [],
+            |row| row.get(0)
+        )?;
+        Ok(count == 0)
+    }
+
+    /// 別の保存先のすべての設定と辞書、接続状態をコピーする
+    pub fn migrate_from(&self, from: &dyn Storage) -> Result<()> {
+        let global = from.load_global()?;
+        let guilds = from.guild_ids()?.into_iter()
+            .map(|guild_id| Ok((guild_id, from.load_guild(guild_id)?)))
+            .collect::<Result<Vec<_>>>()?;
+        let sessions = from.load_sessions()?;
+
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        save_global(&tx, &global)?;
+        for (guild_id, config) in &guilds {
+            save_guild(&tx, *guild_id, config)?;
+        }
+        save_sessions(&tx, &sessions)?;
+        tx.commit()?;
+
+        info!(guilds = guilds.len(), "migrated config to SQLite");
+        Ok(())
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn load_global(&self) -> Result<GlobalConfig> {
+        let conn = self.conn.lock().unwrap();
+        let config: Option<String> = conn.query_row(
+            "SELECT config FROM global_config WHERE id = 0",
+            [],
+            |row| row.get(0)
+        ).optional()?;
+        match config {
+            Some(config) => Ok(serde_json::from_str(&config)?),
+            None => Ok(GlobalConfig::default())
+        }
+    }
+
+    fn save_global(&self, config: &GlobalConfig) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        save_global(&conn, config)
+    }
+
+    fn guild_ids(&self) -> Result<Vec<GuildId>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT guild_id FROM guild_config")?;
+        let guild_ids = stmt.query_map([], |row| row.get::<_, i64>(0))?
+            .map(|guild_id| Ok(GuildId(guild_id? as u64)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(guild_ids)
+    }
+
+    fn load_guild(&self, guild_id: GuildId) -> Result<GuildConfig> {
+        let conn = self.conn.lock().unwrap();
+        let config: Option<String> = conn.query_row(
+            "SELECT config FROM guild_config WHERE guild_id = ?1",
+            params![guild_id.0 as i64],
+            |row| row.get(0)
+        ).optional()?;
+        let Some(config) = config else {
+            return Ok(GuildConfig::default());
+        };
+
+        let mut stmt = conn.prepare("SELECT key, value, is_regex FROM dictionary WHERE guild_id = ?1 ORDER BY position, rowid")?;
+        let items = stmt.query_map(params![guild_id.0 as i64], |row| {
+            Ok(DictItem { key: row.get(0)?, value: row.get(1)?, is_regex: row.get(2)? })
+        })?.collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(GuildConfig {
+            dictionary: Dictionary::from_items(items),
+            ..GuildConfig::from_value(serde_json::from_str(&config)?)?
+        })
+    }
+
+    fn save_guild(&self, guild_id: GuildId, config: &GuildConfig) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        save_guild(&tx, guild_id, config)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn load_sessions(&self) -> Result<HashMap<GuildId, Session>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT guild_id, session FROM session")?;
+        let sessions = stmt.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?
+            .map(|row| {
+                let (guild_id, session) = row?;
+                Ok((GuildId(guild_id as u64), serde_json::from_str(&session)?))
+            })
+            .collect::<Result<HashMap<_, _>>>()?;
+        Ok(sessions)
+    }
+
+    fn save_sessions(&self, sessions: &HashMap<GuildId, Session>) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        save_sessions(&tx, sessions)?;
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+fn save_global(conn: &Connection, config: &GlobalConfig) -> Result<()> {
+    conn.execute(
+        "INSERT INTO global_config (id, config) VALUES (0, ?1)
+            ON CONFLICT (id) DO UPDATE SET config = excluded.config WHERE config != excluded.config",
+        params![serde_json::to_string(config)?]
+    )?;
+    Ok(())
+}
+
+/// 設定と、辞書の変更があった項目のみを更新する
+fn save_guild(conn: &Connection, guild_id: GuildId, config: &GuildConfig) -> Result<()> {
+    let id = guild_id.0 as i64;
+    conn.execute(
+        "INSERT INTO guild_config (guild_id, config) VALUES (?1, ?2)
+            ON CONFLICT (guild_id) DO UPDATE SET config = excluded.config WHERE config != excluded.config",
+        params![id, serde_json::to_string(config)?]
+    )?;
+
+    let saved = {
+        let mut stmt = conn.prepare("SELECT key, value, is_regex, position FROM dictionary WHERE guild_id = ?1")?;
+        let saved = stmt.query_map(params![id], |row| {
+            Ok((row.get::<_, String>(0)?, (row.get::<_, String>(1)?, row.get::<_, bool>(2)?, row.get::<_, i64>(3)?)))
+        })?.collect::<rusqlite::Result<HashMap<_, _>>>()?;
+        saved
+    };
+
+    for key in saved.keys().filter(|key| !config.dictionary.contains(key)) {
+        conn.execute("DELETE FROM dictionary WHERE guild_id = ?1 AND key = ?2", params![id, key])?;
+    }
+    // 更新した項目は辞書の末尾に移動するので、順番も保存する
+    for (position, item) in config.dictionary.iter().enumerate() {
+        if saved.get(&item.key) == Some(&(item.value.clone(), item.is_regex, position as i64)) {
+            continue;
+        }
+        conn.execute(
+            "INSERT INTO dictionary (guild_id, key, value, is_regex, position) VALUES (?1, ?2, ?3, ?4, ?5)
+                ON CONFLICT (guild_id, key) DO UPDATE
+                SET value = excluded.value, is_regex = excluded.is_regex, position = excluded.position",
+            params![id, item.key, item.value, item.is_regex, position as i64]
+        )?;
+    }
+    Ok(())
+}
+
+/// 接続状態をすべて置き換える
+fn save_sessions(conn: &Connection, sessions: &HashMap<GuildId, Session>) -> Result<()> {
+    conn.execute("DELETE FROM session", [])?;
+    for (guild_id, session) in sessions {
+        conn.execute(
+            "INSERT INTO session (guild_id, session) VALUES (?1, ?2)",
+            params![guild_id.0 as i64, serde_json::to_string(session)?]
+        )?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_sqlite_storage() {
+    let storage = SqliteStorage::open(":memory:").unwrap();
+    let guild_id = GuildId(1);
+    assert!(storage.guild_ids().unwrap().is_empty());
+
+    let mut config = GuildConfig::default();
+    config.voice.speaker_id = 1;
+    config.dictionary.insert(DictItem { key: "zunda".into(), value: "ずんだ".into(), is_regex: false });
+    config.dictionary.insert(DictItem { key: "^w+$".into(), value: "わら".into(), is_regex: true });
+    storage.save_guild(guild_id, &config).unwrap();
+
+    config.dictionary.remove("zunda");
+    config.dictionary.insert(DictItem { key: "^w+$".into(), value: "笑".into(), is_regex: true });
+    storage.save_guild(guild_id, &config).unwrap();
+
+    let loaded = storage.load_guild(guild_id).unwrap();
+    assert_eq!(storage.guild_ids().unwrap(), vec![guild_id]);
+    assert_eq!(loaded.voice.speaker_id, 1);
+    assert_eq!(loaded.dictionary.iter().cloned().collect::<Vec<_>>(), config.dictionary.iter().cloned().collect::<Vec<_>>());
+}
+
+#[test]
+fn test_sqlite_dictionary_order() {
+    let storage = SqliteStorage::open(":memory:").unwrap();
+    let guild_id = GuildId(1);
+    let mut config = GuildConfig::default();
+    config.dictionary.insert(DictItem { key: "a+".into(), value: "x".into(), is_regex: true });
+    config.dictionary.insert(DictItem { key: "b+".into(), value: "c".into(), is_regex: true });
+    storage.save_guild(guild_id, &config).unwrap();
+
+    config.dictionary.insert(DictItem { key: "a+".into(), value: "b".into(), is_regex: true });
+    storage.save_guild(guild_id, &config).unwrap();
+
+    let loaded = storage.load_guild(guild_id).unwrap();
+    assert_eq!(loaded.dictionary.iter().cloned().collect::<Vec<_>>(), config.dictionary.iter().cloned().collect::<Vec<_>>());
+    assert_eq!(loaded.dictionary.apply("a").unwrap(), "b");
+}
+
+#[test]
+fn test_sqlite_sessions() {
+    use serenity::model::id::ChannelId;
+    use std::time::Instant;
+
+    let storage = SqliteStorage::open(":memory:").unwrap();
+    let session = Session { voice_channel: ChannelId(1), text_channel: ChannelId(2), last_activity: Instant::now() };
+    storage.save_sessions(&HashMap::from([(GuildId(1), session), (GuildId(2), session)])).unwrap();
+    storage.save_sessions(&HashMap::from([(GuildId(1), session)])).unwrap();
+
+    let loaded = storage.load_sessions().unwrap();
+    assert_eq!(loaded.keys().collect::<Vec<_>>(), [&GuildId(1)]);
+    assert_eq!((loaded[&GuildId(1)].voice_channel, loaded[&GuildId(1)].text_channel), (ChannelId(1), ChannelId(2)));
+}
@@ -2,11 +2,13 @@ use std::io::Cursor;
 use vvcore::*;
 use once_cell::sync::Lazy;
 use byteorder::{LittleEndian, WriteBytesExt};
+use std::time::Duration;
 use songbird::input::{
     Input,
     Codec,
     Reader,
-    Container
+    Container,
+    Metadata
 };
 
 static VOICEVOX_CORE: Lazy<VoicevoxCore> = Lazy::new(|| {
@@ -49,7 +51,16 @@ pub fn synthesis(text: &str, speaker_id: u32) -> Result<Vec<u8>, ResultCode> {
 
 /// wavデータから`Input`を生成する
 pub fn to_input(data: &[u8]) -> Input {
-    let (_, data) = wav::read(&mut Cursor::new(data)).unwrap();
+    let (header, data) = wav::read(&mut Cursor::new(data)).unwrap();
+    let samples = data.as_sixteen().unwrap().len() as u64;
+    let metadata = Metadata {
+        channels: Some(header.channel_count as u8),
+        sample_rate: Some(header.sampling_rate),
+        duration: Some(Duration::from_millis(
+            samples * 1000 / (header.channel_count as u64 * header.sampling_rate as u64)
+        )),
+        ..Default::default()
+    };
     // `synthesis()`で得られたデータはpcm_s16leである一方で
     // `Reader::from`ではVec<u8>を要求しているのでリトルエンディアンで変換する
     let data = {
@@ -64,6 +75,6 @@ pub fn to_input(data: &[u8]) -> Input {
         Reader::from(data.clone()),
         Codec::Pcm,
         Container::Raw,
-        None
+        Some(metadata)
     )
 }
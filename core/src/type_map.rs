@@ -1,25 +1,54 @@
 use crate::config::Config;
+use crate::rate_limit::RateLimiter;
+use crate::session::Session;
 use std::sync::{Arc, Mutex};
 use std::collections::HashMap;
 use serenity::{
     prelude::*,
-    model::id::{GuildId, ChannelId}
+    model::id::{GuildId, MessageId, UserId}
 };
 
-pub struct TextChannelId;
+pub struct ConfigData;
 
-impl TypeMapKey for TextChannelId {
-    type Value = Arc<Mutex<HashMap<GuildId, ChannelId>>>;
+impl TypeMapKey for ConfigData {
+    type Value = Arc<Config>;
 }
 
-pub struct ConfigData;
+/// ギルドごとのボイスチャンネルへの接続状態
+pub struct VoiceSession;
 
-impl TypeMapKey for ConfigData {
-    type Value = Arc<Mutex<Config>>;
+impl TypeMapKey for VoiceSession {
+    type Value = Arc<Mutex<HashMap<GuildId, Session>>>;
+}
+
+pub struct RateLimit;
+
+impl TypeMapKey for RateLimit {
+    type Value = Arc<Mutex<RateLimiter>>;
 }
 
-pub struct ConnectedChannel;
+/// 読み上げキューに追加した音声の情報
+///
+/// songbirdの`TrackHandle::typemap()`に格納される。
+#[derive(Debug, Clone, Default)]
+pub struct TrackInfo {
+    /// 読み上げ元のメッセージ
+    pub message_ids: Vec<MessageId>,
+    /// メッセージの送信者
+    pub author: Option<UserId>,
+    /// 読み上げる文章
+    pub text: String,
+    /// 時報などのシステムからのお知らせで、他の読み上げより優先する
+    pub priority: bool
+}
+
+impl TrackInfo {
+    /// 優先して読み上げるお知らせ
+    pub fn announcement() -> Self {
+        Self { priority: true, ..Default::default() }
+    }
+}
 
-impl TypeMapKey for ConnectedChannel {
-    type Value = Arc<Mutex<HashMap<GuildId, ChannelId>>>;
+impl TypeMapKey for TrackInfo {
+    type Value = TrackInfo;
 }
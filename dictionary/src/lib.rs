@@ -1,7 +1,9 @@
 mod eng_dic;
 mod util;
+mod lint;
 
 use eng_dic::ENG_DIC;
+pub use lint::Lint;
 use util::{to_narrow, can_construct};
 use std::path::Path;
 use std::collections::{HashSet, HashMap};
@@ -84,6 +86,11 @@ impl Dictionary {
         self.keys.contains(key)
     }
 
+    /// 通常の項目、正規表現の項目の順に返す
+    pub fn iter(&self) -> impl Iterator<Item = &DictItem> {
+        self.items.iter().chain(self.regex_items.iter())
+    }
+
     pub fn get(&self, key: &str) -> Option<&DictItem> {
         if !self.contains(key) {
             return None;
@@ -93,6 +100,16 @@ impl Dictionary {
         )
     }
 
+    /// すべての正規表現の項目がコンパイルできることを確認する
+    pub fn validate(&self) -> Result<()> {
+        for item in &self.regex_items {
+            if let Err(why) = regex::Regex::new(&item.key) {
+                anyhow::bail!("invalid regex `{}`: {why}", item.key);
+            }
+        }
+        Ok(())
+    }
+
     pub fn apply<T: AsRef<str>>(&self, text: T) -> Result<String> {
         // 全角のASCII文字を半角に変換する
         // 全角仮名はそのままで問題ない
@@ -172,7 +189,7 @@ impl Serialize for Dictionary {
     fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
         where
             S: serde::Serializer {
-        let items = self.items.iter().chain(self.regex_items.iter()).collect::<Vec<_>>();
+        let items = self.iter().collect::<Vec<_>>();
         items.serialize(serializer)
     }
 }
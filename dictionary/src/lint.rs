@@ -0,0 +1,152 @@
+use crate::{Dictionary, DictItem};
+use std::fmt;
+use regex::Regex;
+
+/// `Dictionary::lint`で見つかった問題
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Lint {
+    /// 正規表現としてコンパイルできない。辞書全体が適用されなくなる
+    InvalidRegex { key: String, error: String },
+    /// 正規表現が空文字列にマッチする
+    EmptyMatch { key: String },
+    /// 先に適用される他の項目に置換されるため、この項目が適用されないことがある
+    Shadowed { key: String, by: String },
+    /// 置換後の文字列が空
+    EmptyValue { key: String },
+    /// 置換後の文字列が後から適用される他の項目でさらに置換される
+    Chained { key: String, by: String }
+}
+
+impl Lint {
+    /// 辞書が適用できなくなる問題
+    pub fn is_error(&self) -> bool {
+        matches!(self, Self::InvalidRegex { .. })
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidRegex { key, error } => write!(f, "`{key}`: 無効な正規表現です。({error})"),
+            Self::EmptyMatch { key } => write!(f, "`{key}`: 空文字列にマッチする正規表現です。"),
+            Self::Shadowed { key, by } => write!(f, "`{key}`: 先に`{by}`で置換されるため適用されないことがあります。"),
+            Self::EmptyValue { key } => write!(f, "`{key}`: 読みが空なので削除されます。"),
+            Self::Chained { key, by } => write!(f, "`{key}`: 置換後の文字列がさらに`{by}`で置換されます。")
+        }
+    }
+}
+
+/// 項目のキーにマッチするかを判定する
+enum Matcher {
+    Literal(String),
+    Regex(Regex)
+}
+
+impl Matcher {
+    /// 空文字列へのマッチは置換しても変化しないので除く
+    fn is_match(&self, text: &str) -> bool {
+        match self {
+            Self::Literal(key) => text.contains(key.as_str()),
+            Self::Regex(re) => re.find_iter(text).any(|m| !m.is_empty())
+        }
+    }
+}
+
+impl Dictionary {
+    /// 辞書の問題を調べる
+    pub fn lint(&self) -> Vec<Lint> {
+        let mut lints = Vec::new();
+
+        // 無効な正規表現の項目は以降の検査から除く
+        let mut items: Vec<(&DictItem, Matcher)> = Vec::new();
+        for item in self.iter() {
+            if !item.is_regex {
+                items.push((item, Matcher::Literal(item.key.clone())));
+                continue;
+            }
+            match Regex::new(&item.key) {
+                Ok(re) => {
+                    if re.is_match("") {
+                        lints.push(Lint::EmptyMatch { key: item.key.clone() });
+                    }
+                    items.push((item, Matcher::Regex(re)));
+                },
+                Err(why) => lints.push(Lint::InvalidRegex { key: item.key.clone(), error: why.to_string() })
+            }
+        }
+
+        for (item, _) in &items {
+            if item.value.trim().is_empty() {
+                lints.push(Lint::EmptyValue { key: item.key.clone() });
+            }
+        }
+
+        // 正規表現の項目は通常の項目より先に適用される。
+        // 通常の項目同士では、キーの途中で終わる他のキーが先にマッチする
+        for (item, _) in items.iter().filter(|(item, _)| !item.is_regex) {
+            let shadowed_by = items.iter().find(|(other, matcher)| {
+                if other.key == item.key {
+                    return false;
+                }
+                match matcher {
+                    Matcher::Regex(_) => matcher.is_match(&item.key),
+                    Matcher::Literal(other_key) => item.key.match_indices(other_key.as_str())
+                        .any(|(index, _)| index + other_key.len() < item.key.len())
+                }
+            });
+            if let Some((other, _)) = shadowed_by {
+                lints.push(Lint::Shadowed { key: item.key.clone(), by: other.key.clone() });
+            }
+        }
+
+        lints.extend(chains(&items).into_iter().map(|(key, by)| Lint::Chained { key, by }));
+        lints
+    }
+}
+
+/// 置換後の文字列が後から適用される他の項目のキーにマッチする組を返す
+///
+/// 正規表現の項目は順番に1回ずつ適用され、そのあと通常の項目がまとめて1回適用される。
+/// 通常の項目の置換後の文字列は再び置換されないので、置換が循環することはない。
+fn chains(items: &[(&DictItem, Matcher)]) -> Vec<(String, String)> {
+    let mut chains = Vec::new();
+    for (index, (item, _)) in items.iter().enumerate().filter(|(_, (item, _))| item.is_regex) {
+        for (other_index, (other, matcher)) in items.iter().enumerate() {
+            let is_later = !other.is_regex || other_index > index;
+            if is_later && other.key != item.key && matcher.is_match(&item.value) {
+                chains.push((item.key.clone(), other.key.clone()));
+            }
+        }
+    }
+    chains
+}
+
+#[test]
+fn test_lint() {
+    let item = |key: &str, value: &str, is_regex| DictItem { key: key.into(), value: value.into(), is_regex };
+    let dict = Dictionary::from_items(vec![
+        item("(", "かっこ", true),
+        item("w*", "わら", true),
+        item("ずんだもち", "ずんだ餅", false),
+        item("ずんだ", "ずんだ豆", false),
+        item("草", "", false),
+        item("a", "b", false),
+        item("b", "a", false),
+        item("笑+", "草", true),
+        item("^x", "y", true),
+        item("y", "z", true),
+        item("z", "x", true)
+    ]);
+    let lints = dict.lint();
+    assert!(lints.iter().any(|lint| matches!(lint, Lint::InvalidRegex { key, .. } if key == "(")));
+    assert!(lints.contains(&Lint::EmptyMatch { key: "w*".into() }));
+    assert!(lints.contains(&Lint::Shadowed { key: "ずんだもち".into(), by: "ずんだ".into() }));
+    assert!(!lints.iter().any(|lint| matches!(lint, Lint::Shadowed { key, .. } if key == "ずんだ")));
+    assert!(lints.contains(&Lint::EmptyValue { key: "草".into() }));
+    let chains = lints.iter().filter_map(|lint| match lint {
+        Lint::Chained { key, by } => Some((key.as_str(), by.as_str())),
+        _ => None
+    }).collect::<Vec<_>>();
+    assert_eq!(chains, vec![("笑+", "草"), ("^x", "y"), ("y", "z")]);
+    assert!(lints.iter().filter(|lint| lint.is_error()).count() == 1);
+}